@@ -0,0 +1,62 @@
+// The same update over N entities, once joining two hash map managers with query_mut!,
+// once walking an archetype's rows (where the components sit side by side)
+// Run with cargo bench
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ecs::*;
+
+component! {
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct Place {
+        x: f32,
+        y: f32,
+    }
+}
+
+component! {
+    #[derive(Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct Speed {
+        dx: f32,
+        dy: f32,
+    }
+}
+
+archetype! {
+    struct Mover {
+        place: Place,
+        speed: Speed,
+    }
+}
+
+const N: usize = 100_000;
+
+fn movement(c: &mut Criterion) {
+    let em = EntityManager::default();
+    let ids: Vec<Id> = em.spawn_n(N).into_iter().map(Entity::into_id).collect();
+    let speed = Speed { dx: 1.0, dy: 2.0 };
+    let (mut places, mut speeds, mut movers) = (PlaceManager::default(), SpeedManager::default(), Mover::default());
+    for e in &ids {
+        places.add(*e, None);
+        speeds.add(*e, Some(speed));
+        movers.insert(*e, (Place::default(), speed));
+    }
+
+    let mut group = c.benchmark_group("movement");
+    group.bench_function("query_mut", |b| b.iter(|| {
+        for (p, s) in query_mut!(places, speeds) {
+            *p.x += *s.dx;
+            *p.y += *s.dy;
+        }
+    }));
+    group.bench_function("rows_mut", |b| b.iter(|| {
+        for (_, p, s) in movers.rows_mut() {
+            *p.x += *s.dx;
+            *p.y += *s.dy;
+        }
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, movement);
+criterion_main!(benches);
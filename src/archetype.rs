@@ -0,0 +1,253 @@
+use crate::Component;
+
+// Borrows a whole component as its Ref/RefMut, for storages that keep components whole
+// (such as archetype!) rather than splitting them into one map per field
+// component! implements it
+pub trait ComponentFields: Component {
+    fn fields(&self) -> Self::Ref<'_>;
+    fn fields_mut(&mut self) -> Self::RefMut<'_>;
+}
+
+// Storage for entities that all hold the same set of components
+// Where component! keeps one HashMap per field, and joining managers (query!) looks each entity up
+// in every one of them, an archetype keeps each of its components in a Vec, with every entity at
+// the same position (row) in all of them, so walking the rows touches contiguous memory only
+// archetype! {
+//     pub struct Moving {
+//         transform: Transform,
+//         velocity: Velocity,
+//     }
+// }
+// Moving implements ComponentManager for each of its components, so it can be registered like any
+// other manager (once is enough, as removing any one component removes the entity's row)
+// With several impls around, the component has to be spelled out when calling the trait's methods
+// (ComponentManager::<Transform>::get(&moving, e)), which is also why query! can't take an archetype
+// The catch is that an entity is either in the archetype with all of its components, or not at all:
+// Adding any one component to a new entity gives it the default for every other component
+// Removing any one component takes the whole row with it (the other components are dropped)
+// insert and rows/rows_mut deal in whole rows, and are the fastest way in and out
+// Rows are kept sorted by Id (iteration walks them in ascending Id order, as ComponentManager
+// requires), so inserting below the highest Id or removing shifts the following rows over
+// Entities are mostly spawned with ever increasing Ids, which makes the common insert an append
+// The components have to be declared with component! (which implements ComponentFields)
+// #[sync] (first among the struct's attributes) makes the archetype Send, see component!
+#[macro_export]
+macro_rules! archetype {
+    (@observers [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $field:ident: $component:ty ),+ $(,)? }) => {
+        $crate::paste::paste! {
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $(#[$attr])*
+        $vis struct $name {
+            ids: Vec<$crate::Id>,
+            $(
+            $field: Vec<$component>,
+            [< $field _hooks >]: $crate::ComponentHooks<$crate::$observers>,
+            )+
+        }
+        }
+
+        impl $name {
+            // Fills in e's row, or overwrites it if e is already in the archetype
+            $vis fn insert(&mut self, e: $crate::Id, row: ( $( $component, )+ )) {
+                if $crate::rejects_null(e) {
+                    return;
+                }
+
+                let ( $( $field, )+ ) = row;
+                let i = self.row_or_default(e);
+                $( self.$field[i] = $field; )+
+            }
+
+            // Walks the rows in ascending Id order, one (Id, component refs...) tuple per entity
+            $vis fn rows(&self) -> impl Iterator<Item=($crate::Id, $( <$component as $crate::Component>::Ref<'_> ),+)> {
+                $crate::itertools::izip! {
+                    self.ids.iter().copied(),
+                    $( self.$field.iter().map($crate::ComponentFields::fields) ),+
+                }
+            }
+
+            $vis fn rows_mut(&mut self) -> impl Iterator<Item=($crate::Id, $( <$component as $crate::Component>::RefMut<'_> ),+)> {
+                $crate::itertools::izip! {
+                    self.ids.iter().copied(),
+                    $( self.$field.iter_mut().map($crate::ComponentFields::fields_mut) ),+
+                }
+            }
+
+            fn row(&self, e: $crate::Id) -> Option<usize> {
+                self.ids.binary_search(&e).ok()
+            }
+
+            // e's row, inserting one made of default components if it has none
+            fn row_or_default(&mut self, e: $crate::Id) -> usize {
+                let i = match self.ids.binary_search(&e) {
+                    Ok(i) => return i,
+                    Err(i) => i,
+                };
+
+                self.ids.insert(i, e);
+                $( self.$field.insert(i, Default::default()); )+
+                $( self.[< $field _hooks >].added(e); )+
+                i
+            }
+
+            fn shrink_rows(&mut self) {
+                self.ids.shrink_to_fit();
+                $( self.$field.shrink_to_fit(); )+
+            }
+
+            fn remove_row(&mut self, i: usize) {
+                let e = self.ids.remove(i);
+                $( self.$field.remove(i); )+
+                $( self.[< $field _hooks >].removed(e); )+
+            }
+        }
+
+        $(
+        impl $crate::ComponentManager<$component> for $name {
+            fn add(&mut self, e: $crate::Id, c: Option<$component>) {
+                $crate::ComponentManager::<$component>::set(self, e, c.unwrap_or_default());
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<$component> {
+                let i = self.row(e)?;
+                let c = std::mem::take(&mut self.$field[i]);
+                self.remove_row(i);
+                Some(c)
+            }
+
+            fn set(&mut self, e: $crate::Id, c: $component) -> Option<$component> {
+                if let Some(i) = self.row(e) {
+                    return Some(std::mem::replace(&mut self.$field[i], c));
+                }
+
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                let i = self.row_or_default(e);
+                self.$field[i] = c;
+                None
+            }
+
+            // Moving a component over to an entity outside the archetype goes through remove and set,
+            // so the entity it leaves loses its row, and the one it goes to gets defaults for the rest
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                let (Some(ra), Some(rb)) = (self.row(a), self.row(b)) else {
+                    return $crate::swap_by_remove::<$component, _>(self, a, b);
+                };
+
+                self.$field.swap(ra, rb);
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                self.row(e).is_some()
+            }
+
+            fn len(&self) -> usize {
+                self.ids.len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<<$component as $crate::Component>::Ref<'_>> {
+                let i = self.row(e)?;
+                Some($crate::ComponentFields::fields(&self.$field[i]))
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<<$component as $crate::Component>::RefMut<'_>> {
+                let i = self.row(e)?;
+                Some($crate::ComponentFields::fields_mut(&mut self.$field[i]))
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                self.ids.iter().copied()
+            }
+
+            fn iter(&self) -> impl Iterator<Item=<$component as $crate::Component>::Ref<'_>> {
+                self.$field.iter().map($crate::ComponentFields::fields)
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=<$component as $crate::Component>::RefMut<'_>> {
+                self.$field.iter_mut().map($crate::ComponentFields::fields_mut)
+            }
+
+            fn shrink_to_fit(&mut self) {
+                self.shrink_rows();
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.[< $field _hooks >].on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.[< $field _hooks >].on_remove(f);
+            }
+        }
+        )+
+        }
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::archetype!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::archetype!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        struct Place {
+            x: f32,
+            y: f32,
+        }
+    }
+
+    component! {
+        #[derive(Clone, Copy)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        struct Speed {
+            dx: f32,
+            dy: f32,
+        }
+    }
+
+    archetype! {
+        struct Mover {
+            place: Place,
+            speed: Speed,
+        }
+    }
+
+    #[test]
+    fn rows_mut_and_query_mut_agree() {
+        let em = EntityManager::default();
+        let ids: Vec<Id> = em.spawn_n(100).into_iter().map(Entity::into_id).collect();
+        let speed = Speed { dx: 1.0, dy: 2.0 };
+        let (mut places, mut speeds, mut movers) = (PlaceManager::default(), SpeedManager::default(), Mover::default());
+        for e in &ids {
+            places.add(*e, None);
+            speeds.add(*e, Some(speed));
+            movers.insert(*e, (Place::default(), speed));
+        }
+
+        for (p, s) in query_mut!(places, speeds) {
+            *p.x += *s.dx;
+            *p.y += *s.dy;
+        }
+        for (_, p, s) in movers.rows_mut() {
+            *p.x += *s.dx;
+            *p.y += *s.dy;
+        }
+
+        assert!(places.iter().all(|p| *p.x == 1.0 && *p.y == 2.0));
+        assert!(ComponentManager::<Place>::iter(&movers).all(|p| *p.x == 1.0 && *p.y == 2.0));
+    }
+}
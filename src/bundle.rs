@@ -0,0 +1,77 @@
+use crate::{Component, ComponentManager, Id};
+
+// A group of components attached to an entity all at once
+// Bundles are tuples of components (see bundle!), inserted into a matching tuple of managers:
+// let e = em.spawn();
+// bundle!(Transform::default(), Velocity::default()).insert(*e, (&mut transforms, &mut velocities));
+// Each component goes to the manager at the same position, through ComponentManager::add
+// Bundles hold at most 8 components
+pub trait Bundle<M> {
+    fn insert(self, e: Id, managers: M);
+}
+
+macro_rules! impl_bundle {
+    ($( $c:ident $m:ident ),+) => {
+        impl<'m, $( $c: Component, $m: ComponentManager<$c> ),+> Bundle<( $( &'m mut $m, )+ )> for ( $( $c, )+ ) {
+            #[allow(non_snake_case)]
+            fn insert(self, e: Id, managers: ( $( &'m mut $m, )+ )) {
+                let ( $( $c, )+ ) = self;
+                let ( $( $m, )+ ) = managers;
+                $( $m.add(e, Some($c)); )+
+            }
+        }
+    };
+}
+
+impl_bundle!(C0 M0);
+impl_bundle!(C0 M0, C1 M1);
+impl_bundle!(C0 M0, C1 M1, C2 M2);
+impl_bundle!(C0 M0, C1 M1, C2 M2, C3 M3);
+impl_bundle!(C0 M0, C1 M1, C2 M2, C3 M3, C4 M4);
+impl_bundle!(C0 M0, C1 M1, C2 M2, C3 M3, C4 M4, C5 M5);
+impl_bundle!(C0 M0, C1 M1, C2 M2, C3 M3, C4 M4, C5 M5, C6 M6);
+impl_bundle!(C0 M0, C1 M1, C2 M2, C3 M3, C4 M4, C5 M5, C6 M6, C7 M7);
+
+// Groups components into a Bundle, bundle!(a, b, c) is simply the tuple (a, b, c)
+#[macro_export]
+macro_rules! bundle {
+    ($( $c:expr ),+ $(,)?) => {
+        ( $( $c, )+ )
+    };
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        struct Name {
+            first: String,
+            last: String,
+        }
+    }
+
+    component! {
+        struct Age {
+            years: u32,
+        }
+    }
+
+    component! {
+        struct Player {}
+    }
+
+    #[test]
+    fn bundles_reach_every_manager() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let (mut names, mut ages, mut players) = (NameManager::default(), AgeManager::default(), PlayerManager::default());
+        let name = Name { first: String::from("ada"), last: String::from("l") };
+        bundle!(name, Age { years: 36 }, Player).insert(*e, (&mut names, &mut ages, &mut players));
+
+        assert_eq!(names.get(*e).unwrap().first, "ada");
+        assert_eq!(*ages.get(*e).unwrap().years, 36);
+        assert!(players.contains(*e));
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{Component, ComponentManager, Id, Observer};
+
+// Remembers which entities a query matched, for systems that join the same managers every frame
+// On a stable population, query_cached! then walks the remembered Ids instead of joining again
+// The cache goes stale whenever a component is added to or removed from a watched manager
+// (through the managers' observers), so watch every manager the query mentions, excluded ones included:
+// let mut cache = QueryCache::default();
+// cache.watch(&mut transforms);
+// cache.watch(&mut velocities);
+// for (t, v) in query_cached!(cache, transforms, velocities) { ... }
+// A cache belongs to a single query, two different queries can't share one
+pub struct QueryCache {
+    ids: Vec<Id>,
+    stale: Arc<AtomicBool>,
+}
+
+// What watch leaves with the managers
+type Stale = Box<dyn FnMut(Id) + Send>;
+
+// Not derived, a new cache is stale (it has yet to be filled)
+impl Default for QueryCache {
+    fn default() -> Self {
+        Self { ids: Vec::new(), stale: Arc::new(AtomicBool::new(true)) }
+    }
+}
+
+impl QueryCache {
+    // The observers are boxed so that the bound can name them (they are Send, so every manager takes them)
+    pub fn watch<C: Component, M: ComponentManager<C>>(&self, m: &mut M) where Stale: Observer<M::Observers> {
+        m.on_add(self.observer());
+        m.on_remove(self.observer());
+    }
+
+    fn observer(&self) -> Stale {
+        let stale = Arc::clone(&self.stale);
+        Box::new(move |_| stale.store(true, Ordering::Relaxed))
+    }
+
+    // For changes the observers don't hear about, such as a manager that wasn't watched
+    pub fn invalidate(&self) {
+        self.stale.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    // Used by the query_cached! macro, refills the cache with f if it is stale
+    #[doc(hidden)]
+    pub fn ids_or_insert_with(&mut self, f: impl FnOnce() -> Vec<Id>) -> &[Id] {
+        if self.stale.swap(false, Ordering::Relaxed) {
+            self.ids = f();
+        }
+
+        &self.ids
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        struct Hunger {
+            level: u8,
+        }
+    }
+
+    component! {
+        struct Thirst {
+            level: u8,
+        }
+    }
+
+    #[test]
+    fn cached_queries_match_the_join_until_a_manager_changes() {
+        let em = EntityManager::default();
+        let ids: Vec<Id> = em.spawn_n(6).into_iter().map(Entity::into_id).collect();
+        let (mut hunger, mut thirst) = (HungerManager::default(), ThirstManager::default());
+        let mut cache = QueryCache::default();
+        cache.watch(&mut hunger);
+        cache.watch(&mut thirst);
+        for (i, e) in ids.iter().enumerate() {
+            hunger.add(*e, Some(Hunger { level: i as u8 }));
+            if i % 2 == 0 {
+                thirst.add(*e, None);
+            }
+        }
+
+        let joined: Vec<u8> = query!(hunger, thirst).map(|(h, _)| *h.level).collect();
+        let cached: Vec<u8> = query_cached!(cache, hunger, thirst).map(|(h, _)| *h.level).collect();
+        assert_eq!(cached, joined);
+        assert!(!cache.is_stale());
+
+        // changing a component in place leaves the cache be, adding one doesn't
+        *hunger.get_mut(ids[0]).unwrap().level = 9;
+        assert!(!cache.is_stale());
+        thirst.add(ids[1], None);
+        assert!(cache.is_stale());
+        let cached: Vec<u8> = query_cached!(cache, hunger, thirst).map(|(h, _)| *h.level).collect();
+        assert_eq!(cached, [9, 1, 2, 4]);
+    }
+}
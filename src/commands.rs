@@ -0,0 +1,122 @@
+use crate::{Component, ComponentManager, Entity, EntityManager, Id};
+
+// Records changes to a component manager while the manager can't take them (because it is
+// being iterated over, for instance), to be carried out later on with apply
+// let mut commands = Commands::default();
+// for (e, t) in transforms.iter_with_id() {
+//     if *t.y < 0 {
+//         commands.despawn(e);
+//         commands.spawn(Some(Transform::default()));
+//     }
+// }
+// let spawned = commands.apply(&em, &mut transforms);
+// Each buffer only handles one component type, use one buffer per manager
+pub struct Commands<C> {
+    queue: Vec<Command<C>>,
+}
+
+enum Command<C> {
+    Spawn(Option<C>),
+    Despawn(Id),
+    Add(Id, Option<C>),
+    Set(Id, C),
+    Remove(Id),
+}
+
+// Not derived, as that would require C: Default
+// (which components are, but the buffer doesn't need it)
+impl<C> Default for Commands<C> {
+    fn default() -> Self {
+        Self { queue: Vec::new() }
+    }
+}
+
+impl<C: Component> Commands<C> {
+    // Spawns a new entity holding c (or the default component)
+    pub fn spawn(&mut self, c: Option<C>) {
+        self.queue.push(Command::Spawn(c));
+    }
+
+    pub fn despawn(&mut self, e: Id) {
+        self.queue.push(Command::Despawn(e));
+    }
+
+    pub fn add(&mut self, e: Id, c: Option<C>) {
+        self.queue.push(Command::Add(e, c));
+    }
+
+    pub fn set(&mut self, e: Id, c: C) {
+        self.queue.push(Command::Set(e, c));
+    }
+
+    pub fn remove(&mut self, e: Id) {
+        self.queue.push(Command::Remove(e));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Carries out the recorded commands in the order they were recorded, emptying the buffer
+    // Hands back the entities spawned along the way, which live as long as their handles do
+    pub fn apply<'m>(&mut self, em: &'m EntityManager, m: &mut impl ComponentManager<C>) -> Vec<Entity<'m>> {
+        let mut spawned = Vec::new();
+        for command in self.queue.drain(..) {
+            match command {
+                Command::Spawn(c) => {
+                    let e = em.spawn();
+                    m.add(*e, c);
+                    spawned.push(e);
+                }
+                Command::Despawn(e) => em.despawn(e),
+                Command::Add(e, c) => m.add(e, c),
+                Command::Set(e, c) => { m.set(e, c); }
+                Command::Remove(e) => { m.remove(e); }
+            }
+        }
+
+        spawned
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        struct Ammo {
+            rounds: u32,
+        }
+    }
+
+    #[test]
+    fn queued_commands_materialize_on_apply() {
+        let em = EntityManager::default();
+        let ids: Vec<Id> = em.spawn_n(3).into_iter().map(Entity::into_id).collect();
+        let mut ammo = AmmoManager::default();
+        for e in &ids {
+            ammo.add(*e, Some(Ammo { rounds: 1 }));
+        }
+
+        // spent magazines are swapped for fresh ones, mid-iteration
+        let mut commands = Commands::default();
+        for (e, a) in ammo.iter_with_id() {
+            if e == ids[1] {
+                commands.remove(e);
+                commands.spawn(Some(Ammo { rounds: *a.rounds + 9 }));
+            }
+        }
+
+        assert_eq!(em.len(), 3);
+        let spawned = commands.apply(&em, &mut ammo);
+        assert!(commands.is_empty());
+        assert_eq!(em.len(), 4);
+        assert!(!ammo.contains(ids[1]));
+        assert_eq!(*ammo.get(*spawned[0]).unwrap().rounds, 10);
+    }
+}
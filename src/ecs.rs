@@ -1,25 +1,96 @@
-use std::cell::RefCell;
+use std::any::{type_name, Any, TypeId};
+use std::cell::{BorrowError, BorrowMutError, Cell, Ref, RefCell, RefMut};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::panic::Location;
 use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::{Mutex, PoisonError};
 
-pub type Id = usize;
+use crate::FrozenManager;
+
+// Entities are identified by the index of the slot they occupy in the EntityManager,
+// together with the generation of that slot
+// A slot is reused once its entity dies, and its generation is bumped along the way, so an Id
+// held onto past its entity's death never aliases whichever entity occupies the slot next
+// Component managers key on the full Id, so lookups with a stale Id find nothing
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    // The slot index alone, useful for indexing dense storage
+    // Two entities may share an index (one after the other), so this is not an identity
+    pub fn raw(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Stands for "no entity", for Id fields that would otherwise need to be an Option<Id>
+    // Its slot is reserved, so it is never alive, and no entity (nor component) ever has it
+    pub const NULL: Id = EntityId { index: 0, generation: 0 };
+}
+
+pub type Id = EntityId;
+
+// Ids go out as a single number (the index in the high half, the generation in the low one),
+// which lets them key maps in formats that only take string or number keys (JSON)
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntityId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(((self.index as u64) << 32) | self.generation as u64)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EntityId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let n = u64::deserialize(deserializer)?;
+        Ok(EntityId { index: (n >> 32) as u32, generation: n as u32 })
+    }
+}
 
 // Entities are created by EntityManager::spawn
-// To add Components to an Entity, call Component::add
+// To add Components to an Entity, call ComponentManager::add with its Id (or Bundle::insert, for several at once)
 // Entities exist solely to track their lifetime
 // Once an entity is dropped, each of its components is cleaned up
 //
 // To access an Entity's Id to implement ComponentManagers, dereference it
 pub struct Entity<'a> {
     id: Id,
-    cleanup: Box<dyn Fn(Id) + 'a>,
+    // the manager that spawned it, see downgrade
+    world: WorldId,
+    // None once the handle gave up on its entity, see into_id
+    cleanup: Option<Box<dyn Fn(Id) + 'a>>,
 }
 
 impl<'a> Entity<'a> {
     // internal, for use with EntityManager::spawn
     fn new(id: Id, manager: &'a EntityManager) -> Self {
-        let cleanup = Box::new(|n| { manager.destroy(n) });
-        Self { id, cleanup }
+        Self::with_cleanup(id, manager.world_id(), |n| { manager.destroy(n) })
+    }
+
+    // internal, for managers other than EntityManager
+    pub(crate) fn with_cleanup(id: Id, world: WorldId, cleanup: impl Fn(Id) + 'a) -> Self {
+        Self { id, world, cleanup: Some(Box::new(cleanup)) }
+    }
+
+    // Lets go of the handle without destroying the entity, which then lives until it is despawned
+    // (see EntityManager::despawn) or the EntityManager is cleared
+    pub fn into_id(mut self) -> Id {
+        self.cleanup = None;
+        self.id
+    }
+
+    // A reference to the entity that doesn't keep it alive, see WeakEntity
+    pub fn downgrade(&self) -> WeakEntity {
+        WeakEntity { id: self.id, world: self.world }
     }
 }
 
@@ -31,192 +102,5546 @@ impl<'a> Deref for Entity<'a> {
     }
 }
 
+// Handles compare and hash by their Id alone, so they can go in a HashSet (or key a HashMap)
+// which can then be looked up by Id as well, thanks to Borrow
+// The handle's world is left out on purpose: Borrow requires a handle to compare and hash exactly
+// like its Id, which knows nothing of worlds, so handles spawned by different managers compare equal
+// when their Ids do (keep handles of different worlds apart, or key on (WorldId, Id) pairs)
+impl PartialEq for Entity<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Entity<'_> {}
+
+impl std::hash::Hash for Entity<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl std::borrow::Borrow<Id> for Entity<'_> {
+    fn borrow(&self) -> &Id {
+        &self.id
+    }
+}
+
 // Since Drop::drop takes &mut self the cleanup function cannot be FnOnce
 // (which would require a `self` receiver, as opposed to drop's `&mut self`)
 impl<'a> Drop for Entity<'a> {
     fn drop(&mut self) {
-        (self.cleanup)(self.id);
+        if let Some(cleanup) = &self.cleanup {
+            cleanup(self.id);
+        }
     }
 }
 
-// Holds the 'brains' of the entity manager, but, due to the type system's constraints
-// to have the ECS work (specifically: entity cleanup needing to hold a reference to EntityManager),
-// requires interior mutability, but this constraint should be transparent to the user
-// For API use, see EntityManager
-#[derive(Default)]
-struct EntityManagerCore<'a> {
-    counter: Id,
-    component_cleanups: Vec<Box<dyn FnMut(Id) + 'a>>,
+// Entity is to WeakEntity what Rc is to Weak: holding onto a WeakEntity doesn't keep the entity
+// alive, and the entity manager tells whether it still is
+// The Id carries the slot's generation, so once the entity dies the WeakEntity stays dead, even
+// after its slot is handed to a newer entity
+// Unlike Weak, upgrading doesn't give back a handle (which would destroy the entity when dropped),
+// only the entity's Id for as long as it's alive
+// It also remembers which manager spawned the entity, any other manager (whose Ids may well
+// coincide with this one's) reports it dead
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WeakEntity {
+    id: Id,
+    world: WorldId,
 }
 
+impl WeakEntity {
+    // The entity's Id, whether or not it's still alive
+    pub fn id(&self) -> Id {
+        self.id
+    }
 
-// EntityManager::default() to create
-// EntityManager must outlive components registered with register_component
-// It is recommended to use EntityManager as a global
-#[derive(Default, Clone)]
-pub struct EntityManager<'a>(Rc<RefCell<EntityManagerCore<'a>>>);
-
-impl<'a> EntityManager<'a> {
-    pub fn spawn(&mut self) -> Entity {
-        let mut this = self.0.borrow_mut();
-        let ret = Entity::new(this.counter, self);
-        this.counter += 1;
-        ret
+    // The manager that spawned the entity
+    pub fn world_id(&self) -> WorldId {
+        self.world
     }
 
-    pub fn register_component<C: Component, M: ComponentManager<C>>(&mut self, m: &'a mut M) {
-        let mut this = self.0.try_borrow_mut().unwrap();
-        this.component_cleanups.push(Box::new(|e| m.remove(e)));
+    pub fn is_alive(&self, manager: &EntityManager) -> bool {
+        manager.world_id() == self.world && manager.is_alive(self.id)
     }
 
-    fn destroy(&self, e: Id) {
-        let mut this = self.0.borrow_mut();
-        for c in this.component_cleanups.iter_mut() {
-            c(e);
-        }
+    // The entity's Id, None once it's been destroyed
+    pub fn upgrade(&self, manager: &EntityManager) -> Option<Id> {
+        self.is_alive(manager).then_some(self.id)
     }
 }
 
-// Components store data for entities
-// In memory, components are modeled as SoA (struct of arrays)
-// Consequently, to have a nicer API, for each Component struct,
-// we need a struct of references (Ref) and a struct of mutable references (RefMut)
-// This trait aims to provide a single interface linking all three types
-// It is recommended to use the component! macro to implement components
-//TODO: find a way to enforce that RefMut must also implement From<(anonymous tuple of the component's fields)>
-pub trait Component: Default {
-    type Ref<'a>;
-    type RefMut<'a>;
+// Spawns an entity one component at a time, see EntityManager::spawn_builder
+// let e = em.spawn_builder()
+//     .with(&mut transforms, Transform::default())
+//     .with(&mut velocities, Velocity::default())
+//     .build();
+// Each component goes through ComponentManager::add, and the entity is alive from the start
+// (dropping the builder destroys it, just like dropping the handle would)
+pub struct EntityBuilder<'a> {
+    entity: Entity<'a>,
 }
 
-// ComponentManager is the data store for components, the interface to the SoA
-// effectively, the trait creates a connection between the Component and its storage
-// The recommended use case is:
-// for each field (where field: F) in the Component there should be a matching HashMap<Id, F>
-// Note, however, that it is up to the user to implement the data however they wish
-// as long as the function's contracts are upheld
-pub trait ComponentManager<C: Component> {
-    fn add(&mut self, e: Entity, c: Option<C>);
-    fn remove(&mut self, e: Id);
+impl<'a> EntityBuilder<'a> {
+    pub fn with<C: Component>(self, m: &mut impl ComponentManager<C>, c: C) -> Self {
+        m.add(*self.entity, Some(c));
+        self
+    }
 
-    fn iter(&self) -> impl Iterator<Item=C::Ref<'_>>;
-    fn iter_mut(&mut self) -> impl Iterator<Item=C::RefMut<'_>>;
+    pub fn build(self) -> Entity<'a> {
+        self.entity
+    }
 }
 
-// There is no System trait as there is no need for one!
-// However, for easy use, a system! macro is provided
-// A system can be implemented like so:
-// struct RenderSystem<'a> {
-//     transforms: &'a TransformManager,
-//     meshes: &'a MeshManager,
-// }
-//
-// impl<'a> RenderSystem<'a> {
-//     fn iter(&self) -> impl Iterator<Item=(<Transform as Component>::Ref<'a>, <Mesh as Component>::Ref<'a>)> {
-//         izip!(self.transforms.iter(), self.meshes.iter())
-//     }
-// }
-//
-// impl<'a> RenderSystem<'a> {
-//     fn draw(&self) {
-//         for (t, m) in self.iter() {
-//             // use t,m in here
-//         }
-//     }
+// Despawns every entity spawned through it once dropped, see EntityManager::scope
+// For short-lived groups of entities that go away together (a particle burst, a popup's widgets)
+// let mut burst = em.scope();
+// for _ in 0..100 {
+//     particles.add(burst.spawn(), None);
 // }
+// drop(burst); // every particle is despawned, entities spawned through em itself are left alone
+// Scoped entities don't get handles, the scope stands in for all of them
+// (despawning one of them early is fine, dead Ids are skipped)
+pub struct Scope<'m, 'a> {
+    manager: &'m EntityManager<'a>,
+    ids: Vec<Id>,
+}
 
-// Define your component struct inside the macro to automatically
-// implement the Component trait (and required Ref glue) for it
-// Caveats:
-// The struct must be default-derivable
-// Each field must be incrementally annotated with #[slot(n)] starting at 0
-// The last field must have a comma after it (struct Foo { x: i32, })
-// Does not support struct visibility modifiers (pub struct Foo)
-// Does not support field visibility modifiers (struct Foo { pub x: () })
-// Does not work with generics/lifetimes (struct<T> Foo { ... })
-// Doess not work with tuple structs (struct Foo(...))
-// Does not catch attributes (#[derive(...)] for example)
-#[macro_export]
-macro_rules! component {
-    (struct $name:ident { $( #[slot($s:literal)] $field_name:ident: $field_type:ty, )+ }) => {
-        // implement struct
-        #[derive(Default)]
-        pub struct $name {
-        $( pub $field_name: $field_type ),+
+impl Scope<'_, '_> {
+    pub fn spawn(&mut self) -> Id {
+        let id = self.manager.spawn().into_id();
+        self.ids.push(id);
+        id
+    }
+
+    // See EntityManager::spawn_n
+    pub fn spawn_n(&mut self, count: usize) -> Vec<Id> {
+        let ids: Vec<Id> = self.manager.spawn_n(count).into_iter().map(Entity::into_id).collect();
+        self.ids.extend_from_slice(&ids);
+        ids
+    }
+
+    // The entities spawned through the scope, in spawning order (including those despawned since)
+    pub fn ids(&self) -> &[Id] {
+        &self.ids
+    }
+}
+
+impl Drop for Scope<'_, '_> {
+    fn drop(&mut self) {
+        self.manager.despawn_many(std::mem::take(&mut self.ids));
+    }
+}
+
+// Hands out entity Ids and keeps track of which ones are alive
+pub(crate) struct Slots {
+    // current generation of each slot, indexed by EntityId::index
+    generations: Vec<u32>,
+    // slots of dead entities, up for reuse
+    free: Vec<u32>,
+    live: HashSet<Id>,
+    // lowered by tests, which can't afford 2^32 slots, see max_index
+    #[cfg(test)]
+    max_index: u32,
+}
+
+// The first slot belongs to EntityId::NULL, so entities start at index 1
+impl Default for Slots {
+    fn default() -> Self {
+        Self {
+            generations: vec![0],
+            free: Vec::new(),
+            live: HashSet::new(),
+            #[cfg(test)]
+            max_index: u32::MAX,
         }
+    }
+}
 
-        $crate::paste::paste! {
-        // create Ref/RefMut glue
-        pub struct [< $name Ref >]<'a> {
-        $( pub $field_name: &'a $field_type ),+
+impl Slots {
+    // The highest index to hand out, every path growing the slots has to check it
+    #[cfg(not(test))]
+    fn max_index(&self) -> u32 {
+        u32::MAX
+    }
+
+    #[cfg(test)]
+    fn max_index(&self) -> u32 {
+        self.max_index
+    }
+
+    pub(crate) fn allocate(&mut self) -> Id {
+        self.try_allocate().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub(crate) fn try_allocate(&mut self) -> Result<Id, SpawnError> {
+        let Some(index) = self.free.pop() else {
+            return self.try_allocate_fresh();
+        };
+
+        let id = EntityId { index, generation: self.generations[index as usize] };
+        self.live.insert(id);
+        Ok(id)
+    }
+
+    // Allocates past the last slot, ignoring any free ones
+    pub(crate) fn allocate_fresh(&mut self) -> Id {
+        self.try_allocate_fresh().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub(crate) fn try_allocate_fresh(&mut self) -> Result<Id, SpawnError> {
+        let index = u32::try_from(self.generations.len()).ok().filter(|i| *i <= self.max_index()).ok_or(SpawnError::Exhausted)?;
+        self.generations.push(0);
+
+        let id = EntityId { index, generation: 0 };
+        self.live.insert(id);
+        Ok(id)
+    }
+
+    // Allocates exactly the given Id, or says why it can't
+    // Slots skipped over along the way become free for reuse
+    // A slot's generation only ever goes up: Ids of a generation the slot has moved past were handed
+    // out (and freed) already, bringing them back would resurrect every stale copy of them
+    pub(crate) fn allocate_at(&mut self, id: Id) -> Result<(), &'static str> {
+        let index = id.index as usize;
+        if id.index == EntityId::NULL.index {
+            return Err("its slot is reserved for EntityId::NULL");
         }
 
-        pub struct [< $name RefMut >]<'a> {
-        $( pub $field_name: &'a mut $field_type ),+
+        if id.index > self.max_index() {
+            return Err("its index is past the last one");
         }
 
-        // implement From(tuple) for glue types (iterator support)
-        impl<'a> From<( $(&'a $field_type),+ )> for [< $name Ref >]<'a> {
-            fn from(value: ( $(&'a $field_type),+ )) -> Self {
-                Self {
-                    $(
-                    $field_name: value.$s,
-                    )+
-                }
+        if self.generations.len() <= index {
+            let first = self.generations.len() as u32;
+            self.generations.resize(index + 1, 0);
+            self.free.extend(first..id.index);
+        } else {
+            let current = self.generations[index];
+            if self.is_alive(EntityId { index: id.index, generation: current }) {
+                return Err("its slot is already taken by a live entity");
             }
-        }
 
-        impl<'a> From<( $(&'a mut $field_type),+ )> for [< $name RefMut >]<'a> {
-            fn from(value: ( $(&'a mut $field_type),+ )) -> Self {
-                Self {
-                    $(
-                    $field_name: value.$s,
-                    )+
-                }
+            // see free
+            if current == u32::MAX {
+                return Err("its slot ran out of generations");
+            }
+
+            if id.generation < current {
+                return Err("its slot has moved past its generation");
             }
+
+            self.free.retain(|i| *i != id.index);
         }
 
-        // implement Component
-        impl Component for $name {
-            type Ref<'a> = [< $name Ref >]<'a>;
-            type RefMut<'a> = [< $name RefMut >]<'a>;
+        self.generations[index] = id.generation;
+        self.live.insert(id);
+        Ok(())
+    }
+
+    // Frees e's slot, returning false if e was already dead
+    pub(crate) fn free(&mut self, e: Id) -> bool {
+        if !self.live.remove(&e) {
+            return false;
         }
 
-        // implement SoA
-        #[derive(Default)]
-        pub struct [< $name Manager >] {
-        $( $field_name: std::collections::HashMap<$crate::ecs::Id, $field_type> ),+
+        // a slot that ran out of generations is retired rather than wrapped around,
+        // which would bring back Ids that were handed out before
+        let generation = &mut self.generations[e.index as usize];
+        if let Some(next) = generation.checked_add(1) {
+            *generation = next;
+            self.free.push(e.index);
         }
 
-        // implement ComponentManager for SoA
-        impl ComponentManager<$name> for [< $name Manager >] {
-            fn add(&mut self, e: $crate::ecs::Entity, default: Option<$name>) {
-                let default = default.unwrap_or_default();
-                $(
-                self.$field_name.insert(*e, default.$field_name);
-                )+
-            }
+        true
+    }
 
-            fn remove(&mut self, e: $crate::ecs::Id) {
-                $(
-                self.$field_name.remove(&e);
-                )+
-            }
+    pub(crate) fn is_alive(&self, e: Id) -> bool {
+        self.live.contains(&e)
+    }
 
-            fn iter(&self) -> impl Iterator<Item=<$name as Component>::Ref<'_>>{
-                $crate::itertools::izip! {
-                $( self.$field_name.values() ),+
-                }
-                .map(From::from)
-            }
+    pub(crate) fn len(&self) -> usize {
+        self.live.len()
+    }
 
-            fn iter_mut(&mut self) -> impl Iterator<Item=<$name as Component>::RefMut<'_>>{
-                $crate::itertools::izip! {
-                $(  self.$field_name.values_mut() ),+
-                }
-                .map(From::from)
-            }
+    // In ascending Id order
+    pub(crate) fn ids(&self) -> Vec<Id> {
+        let mut ids: Vec<Id> = self.live.iter().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpawnError {
+    // Every entity index (of which there are 2^32) is taken
+    Exhausted,
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exhausted => write!(f, "entity index space exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegisterError {
+    // The entity manager is borrowed (a resource is being held on to)
+    Borrowed,
+    // An entity is being destroyed, and its components cleaned up
+    Destroying,
+}
+
+impl std::fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Borrowed => write!(f, "cannot register component: the entity manager is already borrowed"),
+            Self::Destroying => write!(f, "cannot register component: an entity is being destroyed"),
         }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddError {
+    // The entity was already destroyed
+    Dead(Id),
+    // The entity already has the component, see ComponentManager::try_add
+    Present(Id),
+}
+
+impl std::fmt::Display for AddError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dead(e) => write!(f, "cannot add a component to {e:?}: the entity is dead"),
+            Self::Present(e) => write!(f, "cannot add a component to {e:?}: the entity already has one"),
         }
-    };
+    }
+}
+
+impl std::error::Error for AddError {}
+
+// See the TryFrom impls generated by component!
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AssembleError {
+    // Two of the fields belong to different entities (the first field's and the odd one's)
+    Mismatched(Id, Id),
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mismatched(a, b) => write!(f, "cannot assemble a component out of fields of both {a:?} and {b:?}"),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// One entity's components, see EntityManager::snapshot_entity
+// Holds one (possibly missing) component per cloneable manager
+pub struct EntitySnapshot {
+    components: Vec<(Registration, Option<Box<dyn Any>>)>,
+}
+
+// Every live entity's components, see EntityManager::snapshot
+// Entities are kept in ascending Id order
+pub struct WorldSnapshot {
+    entities: Vec<(Id, EntitySnapshot)>,
+}
+
+impl WorldSnapshot {
+    // Number of entities captured
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+// What changed between two snapshots of the same world, see EntityManager::diff
+// Every list is in ascending Id order (an entity's components go in registration order)
+// A spawned entity's components count as added, and a despawned one's as removed
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct WorldDiff {
+    pub spawned: Vec<Id>,
+    pub despawned: Vec<Id>,
+    pub added: Vec<(Id, ComponentId)>,
+    pub removed: Vec<(Id, ComponentId)>,
+    // only tracked for managers registered with register_diffable_component
+    pub changed: Vec<(Id, ComponentId)>,
+}
+
+impl WorldDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// The type-erased side of a manager registered with register_cloneable_component
+trait Cloneable {
+    fn copy(&mut self, src: Id, dst: Id);
+    fn snapshot(&mut self, e: Id) -> Option<Box<dyn Any>>;
+    fn restore(&mut self, e: Id, c: Option<Box<dyn Any>>);
+    // Whether two snapshotted components are equal, None if the component can't tell
+    fn same(&self, a: &dyn Any, b: &dyn Any) -> Option<bool>;
+}
+
+// Shares the manager with its cleanup, hence the RefCell
+struct CloneableManager<'a, C, M> {
+    manager: Rc<RefCell<&'a mut M>>,
+    component: PhantomData<fn() -> C>,
+    // set by register_diffable_component
+    same: Option<Same>,
+}
+
+// Compares two type-erased components of the same type
+type Same = fn(&dyn Any, &dyn Any) -> bool;
+
+impl<'a, C, M> Cloneable for CloneableManager<'a, C, M>
+where
+    C: Component + for<'r> From<C::Ref<'r>> + 'static,
+    M: ComponentManager<C>,
+{
+    fn copy(&mut self, src: Id, dst: Id) {
+        self.manager.borrow_mut().copy(src, dst);
+    }
+
+    fn snapshot(&mut self, e: Id) -> Option<Box<dyn Any>> {
+        let c = self.manager.borrow().get(e).map(C::from)?;
+        Some(Box::new(c))
+    }
+
+    fn restore(&mut self, e: Id, c: Option<Box<dyn Any>>) {
+        let mut manager = self.manager.borrow_mut();
+        match c.and_then(|c| c.downcast::<C>().ok()) {
+            Some(c) => { manager.set(e, *c); }
+            None => { manager.remove(e); }
+        }
+    }
+
+    fn same(&self, a: &dyn Any, b: &dyn Any) -> Option<bool> {
+        self.same.map(|same| same(a, b))
+    }
+}
+
+// The type-erased side of a manager registered with register_owned_component
+trait AnyManager {
+    fn component(&self) -> ComponentId;
+    fn contains(&self, e: Id) -> bool;
+    fn remove(&mut self, e: Id);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<M> AnyManager for M
+where
+    M: Manages + ComponentManager<M::Component> + 'static,
+    M::Component: 'static,
+{
+    fn component(&self) -> ComponentId {
+        ComponentId::of::<M::Component>()
+    }
+
+    fn contains(&self, e: Id) -> bool {
+        ComponentManager::contains(self, e)
+    }
+
+    fn remove(&mut self, e: Id) {
+        ComponentManager::remove(self, e);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Holds the 'brains' of the entity manager, but, due to the type system's constraints
+// to have the ECS work (specifically: entity cleanup needing to hold a reference to EntityManager),
+// requires interior mutability, but this constraint should be transparent to the user
+// For API use, see EntityManager
+#[derive(Default)]
+struct EntityManagerCore<'a> {
+    slots: Slots,
+    // kept apart from the rest of the core, so that cleanups may use the manager (see destroy)
+    component_cleanups: Cleanups<'a>,
+    // destroyed entities whose components have yet to be cleaned up
+    pending: VecDeque<Id>,
+    // kept apart from the rest of the core for the same reason as the cleanups (see spawned)
+    spawn_hooks: SpawnHooks<'a>,
+    // spawned entities the spawn hooks have yet to hear about
+    unannounced: VecDeque<Id>,
+    // every registered manager, in registration order
+    registered: Vec<Registered<'a>>,
+    next_registration: u64,
+    // every manager registered with register_cloneable_component, for clone_entity and snapshots
+    cloneables: Vec<(Registration, Box<dyn Cloneable + 'a>)>,
+    // every manager registered with register_owned_component, in registration order
+    owned: Vec<(Registration, Box<dyn AnyManager>)>,
+    // global state, at most one value per type
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    world: WorldId,
+    // see EntityManager::set_strict
+    strict: bool,
+}
+
+impl EntityManagerCore<'_> {
+    // Copies of e's components, tagged with their type so another manager can take them in (see merge)
+    fn copy_components(&mut self, e: Id) -> Vec<(ComponentId, Box<dyn Any>)> {
+        let Self { registered, cloneables, .. } = self;
+        cloneables.iter_mut().filter_map(|(r, c)| {
+            let component = registered.iter().find(|other| other.registration == *r)?.component;
+            Some((component, c.snapshot(e)?))
+        }).collect()
+    }
+
+    // Hands c to the first cloneable manager for its component type, if there is one
+    fn paste_component(&mut self, e: Id, component: ComponentId, c: Box<dyn Any>) {
+        let Self { registered, cloneables, .. } = self;
+        let m = cloneables.iter_mut().find(|(r, _)| {
+            registered.iter().any(|other| other.registration == *r && other.component == component)
+        });
+
+        if let Some((_, m)) = m {
+            m.restore(e, Some(c));
+        }
+    }
+}
+
+// sorted by priority, see register_component_with_priority
+type Cleanups<'a> = Rc<RefCell<Vec<Cleanup<'a>>>>;
+
+// see EntityManager::on_spawn
+type SpawnHooks<'a> = Rc<RefCell<Vec<Box<dyn FnMut(Id) + 'a>>>>;
+
+struct Cleanup<'a> {
+    registration: Registration,
+    priority: i32,
+    run: Box<dyn FnMut(Id) + 'a>,
+}
+
+impl<'a> Cleanup<'a> {
+    // Inserts the cleanup after every other one of the same or a lower priority
+    fn insert(self, cleanups: &mut Vec<Cleanup<'a>>) {
+        let i = cleanups.partition_point(|other| other.priority <= self.priority);
+        cleanups.insert(i, self);
+    }
+}
+
+// Identifies one register_component call, see EntityManager::unregister_component
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Registration(pub(crate) u64);
+
+// Tells entity managers apart, as each one hands out Ids of its own (starting from the same index)
+// so an Id is only meaningful alongside the manager it came from, (WorldId, Id) pairs are unique
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WorldId(u64);
+
+// Not derived, every manager gets a new one
+impl Default for WorldId {
+    fn default() -> Self {
+        static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+// Names a component type at runtime, for code that can't name it statically (scripting, editors)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ComponentId(TypeId);
+
+impl ComponentId {
+    pub fn of<C: Component + 'static>() -> Self {
+        Self(TypeId::of::<C>())
+    }
+}
+
+// What the EntityManager knows about a registered manager, besides its cleanup
+struct Registered<'a> {
+    registration: Registration,
+    component: ComponentId,
+    contains: Box<dyn Fn(Id) -> bool + 'a>,
+}
+
+impl<'a> Registered<'a> {
+    fn new<C: Component + 'static, M: ComponentManager<C>>(registration: Registration, m: Rc<RefCell<&'a mut M>>) -> Self {
+        Self {
+            registration,
+            component: ComponentId::of::<C>(),
+            contains: Box::new(move |e| m.try_borrow().is_ok_and(|m| m.contains(e))),
+        }
+    }
+}
+
+// The core's RefCell, plus (in debug builds) where it was last borrowed from
+// A misused resource or a reentrant cleanup otherwise panics with a bare "already borrowed",
+// leaving no clue as to who is holding on to the borrow
+// Only the borrows handed out to the user (resource, manager, events and their _mut) are worth
+// recording, EntityManager's own end before its methods return (and would bury the user's site)
+// They go through try_lend and try_lend_mut, behind #[track_caller] methods, so that the site
+// recorded is the user's rather than somewhere within EntityManager
+// Should the core be borrowed more than once (shared), only the most recent borrow is remembered
+#[derive(Default)]
+struct CoreCell<'a> {
+    core: RefCell<EntityManagerCore<'a>>,
+    borrowed_at: Cell<Option<&'static Location<'static>>>,
+    borrowed_mut_at: Cell<Option<&'static Location<'static>>>,
+}
+
+impl<'a> CoreCell<'a> {
+    fn borrow(&self) -> Ref<'_, EntityManagerCore<'a>> {
+        self.try_borrow().unwrap_or_else(|_| panic!("the entity manager is already mutably borrowed{}", self.conflict()))
+    }
+
+    fn borrow_mut(&self) -> RefMut<'_, EntityManagerCore<'a>> {
+        self.try_borrow_mut().unwrap_or_else(|_| panic!("the entity manager is already borrowed{}", self.conflict()))
+    }
+
+    // Getting the borrow at all means any mutable one has ended
+    fn try_borrow(&self) -> Result<Ref<'_, EntityManagerCore<'a>>, BorrowError> {
+        let this = self.core.try_borrow()?;
+        self.borrowed_mut_at.set(None);
+        Ok(this)
+    }
+
+    // and for a mutable one, that every borrow has
+    fn try_borrow_mut(&self) -> Result<RefMut<'_, EntityManagerCore<'a>>, BorrowMutError> {
+        let this = self.core.try_borrow_mut()?;
+        self.borrowed_at.set(None);
+        self.borrowed_mut_at.set(None);
+        Ok(this)
+    }
+
+    #[track_caller]
+    fn try_lend(&self) -> Result<Ref<'_, EntityManagerCore<'a>>, BorrowError> {
+        let this = self.try_borrow()?;
+        if cfg!(debug_assertions) {
+            self.borrowed_at.set(Some(Location::caller()));
+        }
+
+        Ok(this)
+    }
+
+    #[track_caller]
+    fn try_lend_mut(&self) -> Result<RefMut<'_, EntityManagerCore<'a>>, BorrowMutError> {
+        let this = self.try_borrow_mut()?;
+        if cfg!(debug_assertions) {
+            self.borrowed_mut_at.set(Some(Location::caller()));
+        }
+
+        Ok(this)
+    }
+
+    // Where the borrow in the way was made, to append to a panic message
+    // (empty in release builds, or when EntityManager itself holds the borrow)
+    fn conflict(&self) -> String {
+        let at = if self.core.try_borrow().is_err() {
+            self.borrowed_mut_at.get().map(|l| format!(" (mutably borrowed at {l})"))
+        } else {
+            self.borrowed_at.get().map(|l| format!(" (last borrowed at {l})"))
+        };
+
+        at.unwrap_or_default()
+    }
+}
+
+// EntityManager::default() to create
+// EntityManager must outlive components registered with register_component
+// (or own them instead, see register_owned_component)
+// It is recommended to use EntityManager as a global
+// Every live Entity borrows the manager it was spawned from, so spawning and despawning
+// only take &self (the core's RefCell takes care of the mutation)
+#[derive(Default, Clone)]
+pub struct EntityManager<'a>(Rc<CoreCell<'a>>);
+
+// Prints the number of live entities and of registered managers
+// (or nothing, should the manager be mid-mutation, with its core borrowed)
+impl std::fmt::Debug for EntityManager<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("EntityManager");
+        let Ok(this) = self.0.try_borrow() else {
+            return s.finish_non_exhaustive();
+        };
+
+        s.field("live", &this.slots.len())
+            .field("registered", &(this.registered.len() + this.owned.len()))
+            .finish()
+    }
+}
+
+impl<'a> EntityManager<'a> {
+    // Panics once there are no entity indices left, see try_spawn
+    pub fn spawn(&self) -> Entity<'_> {
+        self.try_spawn().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_spawn(&self) -> Result<Entity<'_>, SpawnError> {
+        let id = self.0.borrow_mut().slots.try_allocate()?;
+        let e = Entity::new(id, self);
+        self.spawned([id]);
+        Ok(e)
+    }
+
+    // Spawns count entities with consecutive indices, all under a single borrow of the core
+    // Freed slots are not reused here, so the indices are always contiguous
+    // The entities live as long as their handles do, so dropping the Vec despawns all of them
+    pub fn spawn_n(&self, count: usize) -> Vec<Entity<'_>> {
+        let ids: Vec<Id> = {
+            let mut this = self.0.borrow_mut();
+            (0..count).map(|_| this.slots.allocate_fresh()).collect()
+        };
+
+        let es = ids.iter().map(|id| Entity::new(*id, self)).collect();
+        self.spawned(ids);
+        es
+    }
+
+    // See EntityBuilder
+    pub fn spawn_builder(&self) -> EntityBuilder<'_> {
+        EntityBuilder { entity: self.spawn() }
+    }
+
+    // In strict mode, destroying an entity while no component manager is registered panics
+    // Entities are usually destroyed for their components to be cleaned up, so that tends to mean
+    // the managers were registered too late (components added before then are never cleaned up)
+    // Off by default, as a world without components is perfectly valid, turn it on during setup
+    pub fn set_strict(&self, strict: bool) {
+        self.0.borrow_mut().strict = strict;
+    }
+
+    // See Scope
+    pub fn scope(&self) -> Scope<'_, 'a> {
+        Scope { manager: self, ids: Vec::new() }
+    }
+
+    // Spawns an entity with the given Id, for restoring a saved world so that stored Ids stay valid
+    // Panics if an entity already occupies the Id's slot (whatever its generation), if the Id
+    // is in EntityId::NULL's slot, or if the slot already went past the Id's generation (the Id
+    // would otherwise come back to life, along with every stale copy of it)
+    // The slot takes on the Id's generation, so restore into a fresh manager, before spawning anything else
+    pub fn spawn_with_id(&self, id: Id) -> Entity<'_> {
+        let allocated = self.0.borrow_mut().slots.allocate_at(id);
+        if let Err(reason) = allocated {
+            panic!("cannot spawn {id:?}: {reason}");
+        }
+
+        let e = Entity::new(id, self);
+        self.spawned([id]);
+        e
+    }
+
+    // Runs f on every entity spawned from then on (right after it is spawned, before the spawning
+    // function returns), to give every new entity a component, say
+    // Should a hook spawn entities of its own, the hooks get to those once they're done with the
+    // current entity, so the nested spawn returns before they do
+    // Panics if called from within a spawn hook
+    pub fn on_spawn(&self, f: impl FnMut(Id) + 'a) {
+        let hooks = Rc::clone(&self.0.borrow().spawn_hooks);
+        let Ok(mut hooks) = hooks.try_borrow_mut() else {
+            panic!("cannot add a spawn hook: an entity is being spawned");
+        };
+
+        hooks.push(Box::new(f));
+    }
+
+    // Runs f on every entity destroyed from then on, before any of its components are cleaned up
+    // (the entity is already dead, but its components are still around)
+    // Panics if called from within a component cleanup or a despawn hook
+    pub fn on_despawn(&self, f: impl FnMut(Id) + 'a) {
+        let mut this = self.0.borrow_mut();
+        let r = Registration(this.next_registration);
+        let Ok(mut cleanups) = this.component_cleanups.try_borrow_mut() else {
+            panic!("cannot add a despawn hook: an entity is being destroyed");
+        };
+
+        Cleanup { registration: r, priority: i32::MIN, run: Box::new(f) }.insert(&mut cleanups);
+        drop(cleanups);
+        this.next_registration += 1;
+    }
+
+    // internal, same as on_despawn, handing f the entity manager as well (for hooks the manager
+    // itself stores, which can't hold on to a clone of it without keeping it alive for good)
+    pub(crate) fn on_despawn_with_manager(&self, mut f: impl FnMut(&EntityManager<'a>, Id) + 'a) {
+        let core = Rc::downgrade(&self.0);
+        self.on_despawn(move |e| {
+            if let Some(core) = core.upgrade() {
+                f(&EntityManager(core), e);
+            }
+        });
+    }
+
+    // Panics if the manager is in use, see try_register_component
+    pub fn register_component<C: Component + 'static, M: ComponentManager<C>>(&mut self, m: &'a mut M) -> Registration {
+        self.try_register_component(m).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Registration can only fail through a clone of this manager: while one of its resources is
+    // borrowed, or from within a component cleanup (while an entity is being destroyed)
+    pub fn try_register_component<C: Component + 'static, M: ComponentManager<C>>(&mut self, m: &'a mut M) -> Result<Registration, RegisterError> {
+        self.try_register_component_with_priority(m, 0)
+    }
+
+    // When an entity is destroyed, its components are removed in ascending priority order
+    // (managers of the same priority go in registration order), for managers that depend on others
+    // A spatial index that needs an entity's transform to unlink it would go first, with a priority
+    // lower than the transforms' (register_component uses 0)
+    pub fn register_component_with_priority<C: Component + 'static, M: ComponentManager<C>>(&mut self, m: &'a mut M, priority: i32) -> Registration {
+        self.try_register_component_with_priority(m, priority).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // (both the cleanup and has_component need the manager, hence the shared RefCell)
+    pub fn try_register_component_with_priority<C: Component + 'static, M: ComponentManager<C>>(&mut self, m: &'a mut M, priority: i32) -> Result<Registration, RegisterError> {
+        let m = Rc::new(RefCell::new(m));
+        let probe = Rc::clone(&m);
+
+        let mut this = self.0.try_borrow_mut().map_err(|_| RegisterError::Borrowed)?;
+        let r = Registration(this.next_registration);
+        let mut cleanups = this.component_cleanups.try_borrow_mut().map_err(|_| RegisterError::Destroying)?;
+        Cleanup { registration: r, priority, run: Box::new(move |e| { m.borrow_mut().remove(e); }) }.insert(&mut cleanups);
+        drop(cleanups);
+        this.next_registration += 1;
+        this.registered.push(Registered::new::<C, M>(r, probe));
+        Ok(r)
+    }
+
+    // Same as register_component, but the manager's components are also copied by clone_entity
+    pub fn register_cloneable_component<C, M>(&mut self, m: &'a mut M) -> Registration
+    where
+        C: Component + for<'r> From<C::Ref<'r>> + 'static,
+        M: ComponentManager<C>,
+    {
+        self.try_register_cloneable_component(m).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_register_cloneable_component<C, M>(&mut self, m: &'a mut M) -> Result<Registration, RegisterError>
+    where
+        C: Component + for<'r> From<C::Ref<'r>> + 'static,
+        M: ComponentManager<C>,
+    {
+        self.try_register_cloneable(m, None)
+    }
+
+    // Same as register_cloneable_component, but diff also reports the manager's components
+    // that changed (not just those added or removed), comparing them with PartialEq
+    pub fn register_diffable_component<C, M>(&mut self, m: &'a mut M) -> Registration
+    where
+        C: Component + for<'r> From<C::Ref<'r>> + PartialEq + 'static,
+        M: ComponentManager<C>,
+    {
+        self.try_register_diffable_component(m).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_register_diffable_component<C, M>(&mut self, m: &'a mut M) -> Result<Registration, RegisterError>
+    where
+        C: Component + for<'r> From<C::Ref<'r>> + PartialEq + 'static,
+        M: ComponentManager<C>,
+    {
+        self.try_register_cloneable(m, Some(|a, b| a.downcast_ref::<C>() == b.downcast_ref::<C>()))
+    }
+
+    fn try_register_cloneable<C, M>(&mut self, m: &'a mut M, same: Option<Same>) -> Result<Registration, RegisterError>
+    where
+        C: Component + for<'r> From<C::Ref<'r>> + 'static,
+        M: ComponentManager<C>,
+    {
+        let m = Rc::new(RefCell::new(m));
+        let copy = Rc::clone(&m);
+        let probe = Rc::clone(&m);
+
+        let mut this = self.0.try_borrow_mut().map_err(|_| RegisterError::Borrowed)?;
+        let r = Registration(this.next_registration);
+        let mut cleanups = this.component_cleanups.try_borrow_mut().map_err(|_| RegisterError::Destroying)?;
+        Cleanup { registration: r, priority: 0, run: Box::new(move |e| { m.borrow_mut().remove(e); }) }.insert(&mut cleanups);
+        drop(cleanups);
+        this.next_registration += 1;
+        this.cloneables.push((r, Box::new(CloneableManager { manager: copy, component: PhantomData::<fn() -> C>, same })));
+        this.registered.push(Registered::new::<C, M>(r, probe));
+        Ok(r)
+    }
+
+    // Same as register_component, but the entity manager takes the manager in, so there's no borrow
+    // to outlive (handy for globals, or for managers created after the entity manager)
+    // The component type is the manager's (see Manages), so it needn't be spelled out
+    // Reach the manager through manager/manager_mut, which borrow the entity manager the way resources
+    // do: spawning or destroying entities while holding on to one panics
+    // There is at most one owned manager of each type, registering another one replaces it
+    // (the old manager is dropped, as if unregistered)
+    // Owned managers are cleaned up after the borrowed ones (regardless of priority), and don't take
+    // part in clone_entity, snapshots or merge
+    pub fn register_owned_component<M>(&self, m: M) -> Registration
+    where
+        M: Manages + ComponentManager<M::Component> + 'static,
+        M::Component: 'static,
+    {
+        self.try_register_owned_component(m).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Only fails while the entity manager is borrowed (Destroying is for borrowed managers)
+    pub fn try_register_owned_component<M>(&self, m: M) -> Result<Registration, RegisterError>
+    where
+        M: Manages + ComponentManager<M::Component> + 'static,
+        M::Component: 'static,
+    {
+        let mut this = self.0.try_borrow_mut().map_err(|_| RegisterError::Borrowed)?;
+        let r = Registration(this.next_registration);
+        this.next_registration += 1;
+        this.owned.retain(|(_, other)| !other.as_any().is::<M>());
+        this.owned.push((r, Box::new(m)));
+        Ok(r)
+    }
+
+    // The owned manager of type M, see register_owned_component
+    #[track_caller]
+    pub fn manager<M: 'static>(&self) -> Option<Ref<'_, M>> {
+        let this = self.0.try_lend().unwrap_or_else(|_| {
+            panic!("cannot borrow manager {}: the entity manager is already mutably borrowed{}", type_name::<M>(), self.0.conflict())
+        });
+
+        Ref::filter_map(this, |this| this.owned.iter().find_map(|(_, m)| m.as_any().downcast_ref())).ok()
+    }
+
+    #[track_caller]
+    pub fn manager_mut<M: 'static>(&self) -> Option<RefMut<'_, M>> {
+        let this = self.0.try_lend_mut().unwrap_or_else(|_| {
+            panic!("cannot mutably borrow manager {}: the entity manager is already borrowed{}", type_name::<M>(), self.0.conflict())
+        });
+
+        RefMut::filter_map(this, |this| this.owned.iter_mut().find_map(|(_, m)| m.as_any_mut().downcast_mut())).ok()
+    }
+
+    // Undoes a registration: the manager no longer hears about destroyed entities (nor takes part
+    // in clone_entity and snapshots), and the closures holding on to it are dropped
+    // Meant for hot reloading, where the manager is about to be replaced
+    // (the manager stays borrowed for as long as the EntityManager's lifetime says so, regardless)
+    // Owned managers are dropped
+    // Returns false if r was already unregistered
+    pub fn unregister_component(&mut self, r: Registration) -> bool {
+        let mut this = self.0.borrow_mut();
+        if let Some(i) = this.owned.iter().position(|(other, _)| *other == r) {
+            this.owned.remove(i);
+            return true;
+        }
+
+        let Some(i) = this.registered.iter().position(|other| other.registration == r) else {
+            return false;
+        };
+
+        this.registered.remove(i);
+        this.cloneables.retain(|(other, _)| *other != r);
+        this.component_cleanups.borrow_mut().retain(|other| other.registration != r);
+        true
+    }
+
+    // Number of component managers registered so far
+    // (a component type registered twice, through two managers, counts twice)
+    pub fn registered_count(&self) -> usize {
+        let this = self.0.borrow();
+        this.registered.len() + this.owned.len()
+    }
+
+    // Whether a manager for C was registered, handy for catching a forgotten register_component
+    pub fn is_registered<C: Component + 'static>(&self) -> bool {
+        self.component_ids().contains(&ComponentId::of::<C>())
+    }
+
+    // The component types of the registered managers, in registration order
+    // (owned managers come after the borrowed ones)
+    pub fn component_ids(&self) -> Vec<ComponentId> {
+        let this = self.0.borrow();
+        let owned = this.owned.iter().map(|(_, m)| m.component());
+        this.registered.iter().map(|r| r.component).chain(owned).collect()
+    }
+
+    // Whether e holds a c component, according to the managers registered for c
+    // (false while the manager is busy cleaning up a destroyed entity)
+    pub fn has_component(&self, e: Id, c: ComponentId) -> bool {
+        let this = self.0.borrow();
+        this.registered.iter().any(|r| r.component == c && (r.contains)(e))
+            || this.owned.iter().any(|(_, m)| m.component() == c && m.contains(e))
+    }
+
+    // Spawns a new entity holding a copy of each of src's components, prefab style
+    // Only managers registered with register_cloneable_component take part, and the copies are
+    // independent of the originals from then on
+    pub fn clone_entity(&self, src: Id) -> Entity<'_> {
+        let dst = self.spawn();
+        for (_, c) in self.0.borrow_mut().cloneables.iter_mut() {
+            c.copy(src, *dst);
+        }
+
+        dst
+    }
+
+    // Captures a copy of each of e's components (for undo, say), see restore_entity
+    // Only managers registered with register_cloneable_component are captured
+    pub fn snapshot_entity(&self, e: Id) -> EntitySnapshot {
+        let components = self.0.borrow_mut().cloneables.iter_mut().map(|(r, c)| (*r, c.snapshot(e))).collect();
+        EntitySnapshot { components }
+    }
+
+    // Captures every live entity's components, see snapshot_entity and diff
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot { entities: self.iter_ids().map(|e| (e, self.snapshot_entity(e))).collect() }
+    }
+
+    // Compares two snapshots of this manager's world, taken at different times (for tests)
+    // let before = em.snapshot();
+    // ... run a system ...
+    // let diff = em.diff(&before, &em.snapshot());
+    // Components are told apart by the manager holding them, so managers unregistered since either
+    // snapshot are ignored, and snapshots of another manager don't compare
+    pub fn diff(&self, before: &WorldSnapshot, after: &WorldSnapshot) -> WorldDiff {
+        let this = self.0.borrow();
+        let component = |r: Registration| this.registered.iter().find(|other| other.registration == r).map(|other| other.component);
+        let same = |r: Registration, a: &dyn Any, b: &dyn Any| {
+            this.cloneables.iter().find(|(other, _)| *other == r).and_then(|(_, m)| m.same(a, b))
+        };
+
+        let mut diff = WorldDiff::default();
+        let old: HashMap<Id, &EntitySnapshot> = before.entities.iter().map(|(e, s)| (*e, s)).collect();
+        let new: HashSet<Id> = after.entities.iter().map(|(e, _)| *e).collect();
+        for (e, s) in &after.entities {
+            let Some(was) = old.get(e) else {
+                diff.spawned.push(*e);
+                diff.added.extend(s.components.iter().filter(|(_, c)| c.is_some()).filter_map(|(r, _)| Some((*e, component(*r)?))));
+                continue;
+            };
+
+            for (r, c) in &s.components {
+                let Some(id) = component(*r) else { continue };
+                let prev = was.components.iter().find(|(other, _)| other == r).and_then(|(_, c)| c.as_deref());
+                match (prev, c.as_deref()) {
+                    (None, Some(_)) => diff.added.push((*e, id)),
+                    (Some(_), None) => diff.removed.push((*e, id)),
+                    (Some(a), Some(b)) if same(*r, a, b) == Some(false) => diff.changed.push((*e, id)),
+                    _ => {}
+                }
+            }
+        }
+
+        for (e, s) in before.entities.iter().filter(|(e, _)| !new.contains(e)) {
+            diff.despawned.push(*e);
+            diff.removed.extend(s.components.iter().filter(|(_, c)| c.is_some()).filter_map(|(r, _)| Some((*e, component(*r)?))));
+        }
+
+        // despawned entities went last, put them back in Id order (sort_by_key is stable)
+        diff.removed.sort_by_key(|(e, _)| *e);
+        diff
+    }
+
+    // Identifies this manager (and its clones, which share its entities) among all others
+    pub fn world_id(&self) -> WorldId {
+        self.0.borrow().world
+    }
+
+    // Moves every entity of other over to this manager, handing back each entity's old Id
+    // alongside its new handle (the Ids are fresh, other's could clash with this manager's own)
+    // Components go across by type, from other's cloneable managers to this manager's
+    // (see register_cloneable_component), and parents are carried over
+    // Other's entities are despawned afterwards, so components without a matching manager
+    // on this side, or held by managers that aren't cloneable, are lost
+    pub fn merge(&self, other: &EntityManager<'_>) -> Vec<(Id, Entity<'_>)> {
+        assert_ne!(self.world_id(), other.world_id(), "cannot merge an entity manager into itself");
+
+        let moved: Vec<(Id, Entity<'_>)> = other.iter_ids().map(|old| (old, self.spawn())).collect();
+        let remap: HashMap<Id, Id> = moved.iter().map(|(old, new)| (*old, **new)).collect();
+        for (old, new) in &moved {
+            let components = other.0.borrow_mut().copy_components(*old);
+            let mut this = self.0.borrow_mut();
+            for (component, c) in components {
+                this.paste_component(**new, component, c);
+            }
+        }
+
+        for (old, new) in &moved {
+            if let Some(parent) = other.parent(*old).and_then(|p| remap.get(&p)) {
+                self.set_parent(**new, *parent);
+            }
+        }
+
+        other.despawn_many(remap.into_keys());
+        moved
+    }
+
+    // Puts the snapshot's components back onto e, which needn't be the entity it was taken from
+    // Components e has that the snapshotted entity didn't are removed, so that e ends up
+    // exactly as the snapshotted entity was (as far as the cloneable managers go)
+    // Managers registered after the snapshot was taken are left alone
+    pub fn restore_entity(&self, e: Id, snapshot: EntitySnapshot) {
+        let mut this = self.0.borrow_mut();
+        for (r, c) in snapshot.components {
+            if let Some((_, m)) = this.cloneables.iter_mut().find(|(other, _)| *other == r) {
+                m.restore(e, c);
+            }
+        }
+    }
+
+    // True until the entity is destroyed, whether by dropping its handle or by despawn
+    pub fn is_alive(&self, e: Id) -> bool {
+        self.0.borrow().slots.is_alive(e)
+    }
+
+    // ComponentManager::add, for Ids that may have outlived their entity (read back from a save,
+    // stashed in another component...), panics if the entity is dead, see try_add_component
+    pub fn add_component<C: Component>(&self, m: &mut impl ComponentManager<C>, e: Id, c: Option<C>) {
+        self.try_add_component(m, e, c).unwrap_or_else(|e| panic!("{e}"));
+    }
+
+    // Leaves the manager alone if the entity is dead, as nothing would ever remove the component
+    pub fn try_add_component<C: Component>(&self, m: &mut impl ComponentManager<C>, e: Id, c: Option<C>) -> Result<(), AddError> {
+        if !self.is_alive(e) {
+            return Err(AddError::Dead(e));
+        }
+
+        m.add(e, c);
+        Ok(())
+    }
+
+    // Number of live entities
+    pub fn len(&self) -> usize {
+        self.0.borrow().slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // All currently alive entities, in ascending Id order
+    // This is a snapshot, the core is not borrowed while iterating (so the loop body may spawn/despawn)
+    pub fn iter_ids(&self) -> impl Iterator<Item=Id> {
+        let ids = self.0.borrow().slots.ids();
+        ids.into_iter()
+    }
+
+    // Resources are global, non-per-entity state (delta time, input state, asset registries...)
+    // There is at most one resource of each type, inserting another one replaces it
+    // A borrowed resource keeps the manager borrowed, so spawning or destroying entities
+    // while holding on to one panics (as would borrowing a resource mutably twice)
+    pub fn insert_resource<T: 'static>(&self, r: T) {
+        self.0.borrow_mut().resources.insert(TypeId::of::<T>(), Box::new(r));
+    }
+
+    #[track_caller]
+    pub fn resource<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        let this = self.0.try_lend().unwrap_or_else(|_| {
+            panic!("cannot borrow resource {}: the entity manager is already mutably borrowed{}", type_name::<T>(), self.0.conflict())
+        });
+
+        Ref::filter_map(this, |this| this.resources.get(&TypeId::of::<T>())?.downcast_ref()).ok()
+    }
+
+    #[track_caller]
+    pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        let this = self.0.try_lend_mut().unwrap_or_else(|_| {
+            panic!("cannot mutably borrow resource {}: the entity manager is already borrowed{}", type_name::<T>(), self.0.conflict())
+        });
+
+        RefMut::filter_map(this, |this| this.resources.get_mut(&TypeId::of::<T>())?.downcast_mut()).ok()
+    }
+
+    // Destroys an entity by Id, for when the Id is stored somewhere instead of the Entity handle
+    // Despawning an Id that is already dead is a no-op
+    // (this includes dropping the handle of an entity that was already despawned by Id)
+    pub fn despawn(&self, e: Id) {
+        self.destroy(e);
+    }
+
+    // Same as despawn for each of the Ids, in order, but borrowing the core only once
+    // Dead Ids are skipped, same as with despawn
+    pub fn despawn_many(&self, ids: impl IntoIterator<Item=Id>) {
+        self.destroy_many(ids);
+    }
+
+    // Despawns every live entity, emptying the registered managers along the way
+    // Slots are reused as usual afterwards, so Ids from before the clear stay dead
+    pub fn clear(&self) {
+        self.destroy_many(self.iter_ids());
+    }
+
+    // Same as clear, but also forgets every slot, so Ids start over from the first index
+    // Ids (and handles) from before the reset alias the entities spawned after it,
+    // so only reset once nothing holds on to them anymore (between test cases, for instance)
+    pub fn reset(&self) {
+        self.clear();
+        let mut this = self.0.borrow_mut();
+        this.slots = Slots::default();
+    }
+
+    // The core isn't borrowed while the cleanups run, so they (the managers' remove) may
+    // despawn further entities, spawn, or use resources
+    // (owned managers are out of the core while they're cleaned up, manager finds none of them)
+    // Entities destroyed from within a cleanup die right away, but are only cleaned up once
+    // the cleanups of the entity currently being destroyed are done
+    //
+    // Children are destroyed along with their parent (and theirs along with them, and so on),
+    // see set_parent
+    fn destroy(&self, e: Id) {
+        self.destroy_many([e]);
+    }
+
+    // Same as destroy, freeing all of the entities under a single borrow of the core
+    fn destroy_many(&self, ids: impl IntoIterator<Item=Id>) {
+        let cleanups = {
+            let mut this = self.0.borrow_mut();
+            for e in ids {
+                if this.slots.free(e) {
+                    this.pending.push_back(e);
+                }
+            }
+
+            if this.pending.is_empty() {
+                return;
+            }
+
+            // (not while unwinding, panicking in a handle's drop then would abort)
+            if this.strict && this.registered.is_empty() && this.owned.is_empty() && !std::thread::panicking() {
+                let e = this.pending[0];
+                this.pending.clear();
+                drop(this);
+                panic!("despawned {e:?} before any component manager was registered (see EntityManager::set_strict)");
+            }
+
+            Rc::clone(&this.component_cleanups)
+        };
+
+        // already borrowed further up the stack, which will get to these as well
+        let Ok(mut cleanups) = cleanups.try_borrow_mut() else {
+            return;
+        };
+
+        while let Some(e) = self.next_pending() {
+            for c in cleanups.iter_mut() {
+                (c.run)(e);
+            }
+
+            // taken out for the while, so their hooks may use the entity manager as well
+            let mut owned = std::mem::take(&mut self.0.borrow_mut().owned);
+            for (_, m) in owned.iter_mut() {
+                m.remove(e);
+            }
+
+            // managers registered by the hooks meanwhile replace ours of the same type
+            let mut this = self.0.borrow_mut();
+            let added = std::mem::take(&mut this.owned);
+            owned.retain(|(_, m)| !added.iter().any(|(_, other)| other.as_any().type_id() == m.as_any().type_id()));
+            owned.extend(added);
+            this.owned = owned;
+        }
+    }
+
+    fn next_pending(&self) -> Option<Id> {
+        self.0.borrow_mut().pending.pop_front()
+    }
+
+    // Runs the spawn hooks on new entities, mirroring destroy_many's cleanups
+    fn spawned(&self, ids: impl IntoIterator<Item=Id>) {
+        let hooks = {
+            let mut this = self.0.borrow_mut();
+            if this.spawn_hooks.try_borrow().is_ok_and(|hooks| hooks.is_empty()) {
+                return;
+            }
+
+            this.unannounced.extend(ids);
+            Rc::clone(&this.spawn_hooks)
+        };
+
+        // already borrowed further up the stack, which will get to these as well
+        let Ok(mut hooks) = hooks.try_borrow_mut() else {
+            return;
+        };
+
+        while let Some(e) = self.next_unannounced() {
+            for h in hooks.iter_mut() {
+                h(e);
+            }
+        }
+    }
+
+    fn next_unannounced(&self) -> Option<Id> {
+        self.0.borrow_mut().unannounced.pop_front()
+    }
+}
+
+// Components store data for entities
+// In memory, components are modeled as SoA (struct of arrays)
+// Consequently, to have a nicer API, for each Component struct,
+// we need a struct of references (Ref) and a struct of mutable references (RefMut)
+// This trait aims to provide a single interface linking all three types
+// It is recommended to use the component! macro to implement components
+//TODO: find a way to enforce that RefMut must also implement From<(anonymous tuple of the component's fields)>
+pub trait Component {
+    type Ref<'a>;
+    type RefMut<'a>;
+}
+
+// ComponentManager is the data store for components, the interface to the SoA
+// effectively, the trait creates a connection between the Component and its storage
+// The recommended use case is:
+// for each field (where field: F) in the Component there should be a matching HashMap<Id, F>
+// These field maps are expected to always hold the same set of Ids (add and remove touch all of them),
+// which is what lets the join in iter/iter_mut line fields back up, and lets contains check just one map
+// Note, however, that it is up to the user to implement the data however they wish
+// as long as the function's contracts are upheld
+pub trait ComponentManager<C: Component> {
+    // Attaches the component (or its default) to the entity
+    // Components without a default (see component!'s #[no_default]) panic when given None
+    // Takes the entity's Id rather than its handle, so the same handle can be given components
+    // from several managers (add(*e, ...)) and the entity stays alive for as long as the handle does
+    // Managers don't know which entities are alive: e must be, otherwise the component is orphaned
+    // (its entity's cleanup already ran, nothing will ever remove it)
+    // EntityManager::add_component checks that for Ids that didn't come straight from a handle
+    fn add(&mut self, e: Id, c: Option<C>);
+
+    // add overwrites whatever component e already has, this fails instead (leaving it be),
+    // for when adding twice would be a logic error rather than an update
+    // (add is to try_add what HashMap::insert is to its entry API)
+    fn try_add(&mut self, e: Id, c: Option<C>) -> Result<(), AddError> {
+        if self.contains(e) {
+            return Err(AddError::Present(e));
+        }
+
+        self.add(e, c);
+        Ok(())
+    }
+
+    // Adds a default component, after letting f adjust it (to set a field or two, say)
+    fn add_with(&mut self, e: Id, f: impl FnOnce(&mut C)) where C: Default {
+        let mut c = C::default();
+        f(&mut c);
+        self.add(e, Some(c));
+    }
+
+    // Adds the component f computes from the entity's Id, for defaults that depend on it (a seed, say)
+    fn add_default_with_id(&mut self, e: Id, f: impl FnOnce(Id) -> C) {
+        self.add(e, Some(f(e)));
+    }
+
+    // Detaches the component from the entity, handing it back (None if the entity didn't have one)
+    fn remove(&mut self, e: Id) -> Option<C>;
+    // Overwrites the entity's component in place, handing back the previous value (if any)
+    fn set(&mut self, e: Id, c: C) -> Option<C>;
+
+    // Detaches the component from every entity holding it (the entities themselves stay alive)
+    // on_remove observers still hear about each of them
+    // The default removes them one by one, managers are encouraged to do better
+    fn clear(&mut self) {
+        let ids: Vec<Id> = self.ids().collect();
+        for e in ids {
+            self.remove(e);
+        }
+    }
+
+    // Removes every component, handing them back along with their entities (in ascending Id order)
+    // The manager is empty once drain returns, whether or not the iterator is consumed
+    fn drain(&mut self) -> impl Iterator<Item=(Id, C)> {
+        let ids: Vec<Id> = self.ids().collect();
+        let drained: Vec<(Id, C)> = ids.into_iter().filter_map(|e| Some((e, self.remove(e)?))).collect();
+        drained.into_iter()
+    }
+
+    // Sets every (entity, component) pair, the counterpart to drain (loading saved data, say)
+    fn extend(&mut self, iter: impl IntoIterator<Item=(Id, C)>) {
+        for (e, c) in iter {
+            self.set(e, c);
+        }
+    }
+
+    // Removes the components for which f returns false (through remove, observers included)
+    fn retain(&mut self, mut f: impl FnMut(Id, C::Ref<'_>) -> bool) {
+        let doomed: Vec<Id> = self.iter_with_id().filter_map(|(e, c)| (!f(e, c)).then_some(e)).collect();
+        for e in doomed {
+            self.remove(e);
+        }
+    }
+
+    fn contains(&self, e: Id) -> bool;
+
+    // Number of entities holding this component
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // None unless every field of the component is present for the entity
+    fn get(&self, e: Id) -> Option<C::Ref<'_>>;
+    fn get_mut(&mut self, e: Id) -> Option<C::RefMut<'_>>;
+
+    // The component of the one entity holding it, for components there should only ever be one of
+    // (the active camera, say), None if there are none
+    // Having more than one is a bug, which panics in debug builds (release builds return None)
+    fn single(&self) -> Option<C::Ref<'_>> {
+        debug_assert!(self.len() <= 1, "expected at most one {}, found {}", type_name::<C>(), self.len());
+        if self.len() != 1 {
+            return None;
+        }
+
+        self.iter().next()
+    }
+
+    fn single_mut(&mut self) -> Option<C::RefMut<'_>> {
+        debug_assert!(self.len() <= 1, "expected at most one {}, found {}", type_name::<C>(), self.len());
+        if self.len() != 1 {
+            return None;
+        }
+
+        self.iter_mut().next()
+    }
+
+    // get_mut for several entities at once, such as for swapping two entities' components
+    // None if any of the entities lacks the component, or if the same entity is asked for twice
+    // (the default walks the whole manager, the generated managers look the entities up directly)
+    fn get_many_mut<const N: usize>(&mut self, ids: [Id; N]) -> Option<[C::RefMut<'_>; N]> {
+        if !disjoint(&ids) {
+            return None;
+        }
+
+        let mut found: [Option<C::RefMut<'_>>; N] = std::array::from_fn(|_| None);
+        for (e, c) in self.iter_mut_with_id() {
+            if let Some(i) = ids.iter().position(|other| *other == e) {
+                found[i] = Some(c);
+            }
+        }
+
+        if found.iter().any(Option::is_none) {
+            return None;
+        }
+
+        Some(found.map(Option::unwrap))
+    }
+
+    // Exchanges a's and b's components, if only one of them has one it moves over to the other
+    // The default goes through remove and set (so observers hear about it), the generated managers
+    // swap the fields in place when both entities have a component
+    fn swap(&mut self, a: Id, b: Id) {
+        swap_by_remove(self, a, b);
+    }
+
+    // ids, iter and iter_mut must all walk the entities in ascending Id order
+    // This keeps iteration deterministic (the same components are always visited in the same order,
+    // from one run to the next, which lockstep networking and replays depend on)
+    // and is what query_mut! joins managers on
+    fn ids(&self) -> impl Iterator<Item=Id>;
+    fn iter(&self) -> impl Iterator<Item=C::Ref<'_>>;
+    fn iter_mut(&mut self) -> impl Iterator<Item=C::RefMut<'_>>;
+
+    // Same as iter/iter_mut, tagging each component with the entity it belongs to
+    // (which relies on ids walking the entities in the same order)
+    fn iter_with_id(&self) -> impl Iterator<Item=(Id, C::Ref<'_>)> {
+        self.ids().zip(self.iter())
+    }
+
+    fn iter_mut_with_id(&mut self) -> impl Iterator<Item=(Id, C::RefMut<'_>)> {
+        let ids: Vec<Id> = self.ids().collect();
+        ids.into_iter().zip(self.iter_mut())
+    }
+
+    // Copies src's component (if it has one) over to dst, overwriting whatever dst had
+    // Requires every field of the component to be Clone, which component! expresses as a
+    // conversion from the component's Ref
+    fn copy(&mut self, src: Id, dst: Id) where for<'r> C: From<C::Ref<'r>> {
+        if let Some(c) = self.get(src).map(C::from) {
+            self.set(dst, c);
+        }
+    }
+
+    // Borrows the manager as a FrozenManager, for handing it to code that must only read from it
+    fn freeze(&self) -> FrozenManager<'_, Self> where Self: Sized {
+        FrozenManager::new(self)
+    }
+
+    // get for small Copy components (a single f32, say), handing back the value rather than a Ref
+    fn get_copied(&self, e: Id) -> Option<C> where C: Copy, for<'r> C: From<C::Ref<'r>> {
+        self.get(e).map(C::from)
+    }
+
+    // Clones every component out of the manager, paired with its entity (in ascending Id order)
+    // For logging and assertions, where borrowing the manager would get in the way
+    fn collect_owned(&self) -> Vec<(Id, C)> where for<'r> C: From<C::Ref<'r>> {
+        self.iter_with_id().map(|(e, c)| (e, C::from(c))).collect()
+    }
+
+    // The same copy, under the name it goes by when looping over a manager that gets mutated
+    // along the way (the loop walks the copy, so components can be added and removed freely)
+    // for (e, t) in transforms.iter_snapshot() { ... transforms.remove(e) ... }
+    fn iter_snapshot(&self) -> Vec<(Id, C)> where for<'r> C: From<C::Ref<'r>> {
+        self.collect_owned()
+    }
+
+    // Hands back whatever memory the manager holds on to beyond what its components need
+    // (after a despawn wave, say), the default does nothing
+    fn shrink_to_fit(&mut self) {}
+
+    // Observers, called with the entity's Id after a component is attached to it with add,
+    // or after it is detached from it with remove (which includes the entity being destroyed)
+    // Any closure will do, unless the manager is to be handed to a SyncEntityManager, see Observers
+    type Observers: Observers;
+    fn on_add(&mut self, f: impl Observer<Self::Observers>);
+    fn on_remove(&mut self, f: impl Observer<Self::Observers>);
+}
+
+// ComponentManager::swap's default, which the generated managers fall back on when moving
+#[doc(hidden)]
+pub fn swap_by_remove<C: Component, M: ComponentManager<C> + ?Sized>(m: &mut M, a: Id, b: Id) {
+    if a == b {
+        return;
+    }
+
+    let (ca, cb) = (m.remove(a), m.remove(b));
+    if let Some(c) = ca {
+        m.set(b, c);
+    }
+
+    if let Some(c) = cb {
+        m.set(a, c);
+    }
+}
+
+// Whether every slot given matches its position, see component!
+#[doc(hidden)]
+pub const fn slots_in_order(slots: &[Option<usize>]) -> bool {
+    let mut i = 0;
+    while i < slots.len() {
+        if let Some(s) = slots[i] {
+            if s != i {
+                return false;
+            }
+        }
+
+        i += 1;
+    }
+
+    true
+}
+
+// Whether no Id appears twice, see ComponentManager::get_many_mut
+#[doc(hidden)]
+pub fn disjoint(ids: &[Id]) -> bool {
+    ids.iter().enumerate().all(|(i, e)| !ids[..i].contains(e))
+}
+
+// Copies every component of src over to dst, converting it with f and keeping it on the same entity
+// For data model changes (splitting or merging components): the old manager is only read from,
+// so it can be dropped once everything has been carried over
+pub fn migrate<A: Component, B: Component>(src: &impl ComponentManager<A>, dst: &mut impl ComponentManager<B>, f: impl Fn(A::Ref<'_>) -> B) {
+    for (e, c) in src.iter_with_id() {
+        dst.set(e, f(c));
+    }
+}
+
+// Pairs up the components of entities found in both managers, in ascending Id order
+// The functional counterpart to query!(a, b), for those who'd rather not use macros:
+// for (e, t, v) in join2(&transforms, &velocities) { ... }
+// Ids are taken from the smaller manager and looked up in the other, same as query! does
+pub fn join2<'m, A: Component, B: Component>(a: &'m impl ComponentManager<A>, b: &'m impl ComponentManager<B>)
+    -> impl Iterator<Item=(Id, A::Ref<'m>, B::Ref<'m>)> + 'm {
+    let ids = smallest_ids([(a.len(), &|| a.ids().collect()), (b.len(), &|| b.ids().collect())]);
+    ids.into_iter().filter_map(move |e| Some((e, a.get(e)?, b.get(e)?)))
+}
+
+// Same as join2, for three managers
+pub fn join3<'m, A: Component, B: Component, C: Component>(a: &'m impl ComponentManager<A>, b: &'m impl ComponentManager<B>, c: &'m impl ComponentManager<C>)
+    -> impl Iterator<Item=(Id, A::Ref<'m>, B::Ref<'m>, C::Ref<'m>)> + 'm {
+    let ids = smallest_ids([
+        (a.len(), &|| a.ids().collect()),
+        (b.len(), &|| b.ids().collect()),
+        (c.len(), &|| c.ids().collect()),
+    ]);
+
+    ids.into_iter().filter_map(move |e| Some((e, a.get(e)?, b.get(e)?, c.get(e)?)))
+}
+
+// Same as join2, for four managers
+pub fn join4<'m, A: Component, B: Component, C: Component, D: Component>(a: &'m impl ComponentManager<A>, b: &'m impl ComponentManager<B>, c: &'m impl ComponentManager<C>, d: &'m impl ComponentManager<D>)
+    -> impl Iterator<Item=(Id, A::Ref<'m>, B::Ref<'m>, C::Ref<'m>, D::Ref<'m>)> + 'm {
+    let ids = smallest_ids([
+        (a.len(), &|| a.ids().collect()),
+        (b.len(), &|| b.ids().collect()),
+        (c.len(), &|| c.ids().collect()),
+        (d.len(), &|| d.ids().collect()),
+    ]);
+
+    ids.into_iter().filter_map(move |e| Some((e, a.get(e)?, b.get(e)?, c.get(e)?, d.get(e)?)))
+}
+
+// The Ids of the manager with the fewest components, given each manager's len and a way to collect its Ids
+fn smallest_ids<const N: usize>(managers: [(usize, &dyn Fn() -> Vec<Id>); N]) -> Vec<Id> {
+    let order = driving_order(managers.each_ref().map(|(len, _)| *len));
+    order.first().map_or_else(Vec::new, |i| (managers[*i].1)())
+}
+
+// The order in which to visit the managers of a hand-written join, given their lens:
+// each manager's position, from the smallest manager to the largest (ties keep their order)
+// let order = driving_order([transforms.len(), velocities.len(), meshes.len()]);
+// Walking the manager at order[0] and looking its entities up in the others does the fewest lookups,
+// which is what join2 and query! do
+pub fn driving_order<const N: usize>(lens: [usize; N]) -> [usize; N] {
+    let mut order = std::array::from_fn(|i| i);
+    order.sort_by_key(|i| lens[*i]);
+    order
+}
+
+// Names the component a manager stores, for code that only knows the manager's type (such as system!)
+// The managers generated by component! implement it
+pub trait Manages {
+    type Component: Component;
+}
+
+// What a manager's observers may be, see ComponentManager::on_add
+// Observers usually stay on the thread their manager lives on, so they may hold on to anything
+// (an Rc, a clone of the EntityManager...), which makes a manager holding them neither Send nor Sync
+// A SyncEntityManager needs Send managers, as whichever thread destroys an entity cleans up its
+// components, so the managers handed to one take SendObservers instead, closures that are Send
+// themselves (the component! family of macros generate those when given #[sync], see component!)
+pub trait Observers {
+    type Observer: FnMut(Id) + ?Sized;
+}
+
+pub struct LocalObservers;
+
+impl Observers for LocalObservers {
+    type Observer = dyn FnMut(Id);
+}
+
+pub struct SendObservers;
+
+impl Observers for SendObservers {
+    type Observer = dyn FnMut(Id) + Send;
+}
+
+// Closures that fit among K's observers, every FnMut(Id) + 'static for LocalObservers,
+// and only the Send ones for SendObservers
+pub trait Observer<K: Observers>: FnMut(Id) + 'static {
+    fn boxed(self) -> Box<K::Observer>;
+}
+
+impl<F: FnMut(Id) + 'static> Observer<LocalObservers> for F {
+    fn boxed(self) -> Box<dyn FnMut(Id)> {
+        Box::new(self)
+    }
+}
+
+impl<F: FnMut(Id) + Send + 'static> Observer<SendObservers> for F {
+    fn boxed(self) -> Box<dyn FnMut(Id) + Send> {
+        Box::new(self)
+    }
+}
+
+// Storage for a ComponentManager's observers, see ComponentManager::on_add/on_remove
+// They are kept behind a Mutex purely to make managers taking SendObservers Sync as well:
+// observers only ever run through &mut self, so the Mutex is never actually locked (see Mutex::get_mut)
+pub struct ComponentHooks<K: Observers = LocalObservers> {
+    on_add: Hooks<K>,
+    on_remove: Hooks<K>,
+}
+
+type Hooks<K> = Mutex<Vec<Box<<K as Observers>::Observer>>>;
+
+// Not derived, as that would require K: Default
+impl<K: Observers> Default for ComponentHooks<K> {
+    fn default() -> Self {
+        Self { on_add: Mutex::default(), on_remove: Mutex::default() }
+    }
+}
+
+// Observers are closures, which can't be saved, so a manager loads without any
+#[cfg(feature = "serde")]
+impl<K: Observers> serde::Serialize for ComponentHooks<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K: Observers> serde::Deserialize<'de> for ComponentHooks<K> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <()>::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
+
+impl<K: Observers> ComponentHooks<K> {
+    pub fn on_add(&mut self, f: impl Observer<K>) {
+        Self::hooks(&mut self.on_add).push(f.boxed());
+    }
+
+    pub fn on_remove(&mut self, f: impl Observer<K>) {
+        Self::hooks(&mut self.on_remove).push(f.boxed());
+    }
+
+    // To be called by the ComponentManager once e's component has been added
+    pub fn added(&mut self, e: Id) {
+        for f in Self::hooks(&mut self.on_add).iter_mut() {
+            f(e);
+        }
+    }
+
+    // To be called by the ComponentManager once e's component has been removed
+    pub fn removed(&mut self, e: Id) {
+        for f in Self::hooks(&mut self.on_remove).iter_mut() {
+            f(e);
+        }
+    }
+
+    fn hooks(hooks: &mut Hooks<K>) -> &mut Vec<Box<K::Observer>> {
+        // a panicking observer leaves the list itself intact
+        hooks.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Systems hold on to the component managers they work on and run once per frame (or tick)
+// The System trait gives all of them a common interface, so that a scheduler can
+// keep heterogeneous systems in a Vec<Box<dyn System>> without knowing their concrete types
+// For easy use, a system! macro is provided, defining the struct and a joined iter over its managers:
+// system! {
+//     struct RenderSystem {
+//         transforms: TransformManager,
+//         meshes: MeshManager,
+//     }
+// }
+//
+// which amounts to:
+// struct RenderSystem<'a> {
+//     transforms: &'a TransformManager,
+//     meshes: &'a MeshManager,
+// }
+//
+// impl<'a> RenderSystem<'a> {
+//     fn iter(&self) -> impl Iterator<Item=(<Transform as Component>::Ref<'a>, <Mesh as Component>::Ref<'a>)> {
+//         query!(self.transforms, self.meshes)
+//     }
+// }
+//
+// leaving only System itself to implement:
+// impl<'a> System for RenderSystem<'a> {
+//     fn run(&mut self) {
+//         for (t, m) in self.iter() {
+//             // use t,m in here
+//         }
+//     }
+// }
+//
+// And then driven, alongside any other system, like so:
+// let mut systems: Vec<Box<dyn System>> = vec![
+//     Box::new(PhysicsSystem { transforms: &mut transforms, velocities: &velocities }),
+//     Box::new(RenderSystem { transforms: &transforms, meshes: &meshes }),
+// ];
+//
+// loop {
+//     for s in &mut systems {
+//         s.run();
+//     }
+// }
+pub trait System {
+    fn run(&mut self);
+}
+
+// Used by the generated managers, not meant to be called directly
+// EntityId::NULL never holds a component, so adding one to it is a bug: debug builds panic,
+// release builds ignore the component (contains(NULL) stays false either way)
+#[doc(hidden)]
+#[track_caller]
+pub fn rejects_null(e: Id) -> bool {
+    debug_assert!(e != EntityId::NULL, "cannot add a component to EntityId::NULL");
+    e == EntityId::NULL
+}
+
+// Used by the component! macro, not meant to be called directly
+// Each field of a component lives in its own HashMap, and two HashMaps holding the same keys
+// make no promise to iterate them in the same order, so zipping their values() would pair up
+// fields belonging to different entities. Walking every field map in ascending Id order keeps
+// the fields of each entity lined up (this relies on all field maps holding the same Ids)
+#[doc(hidden)]
+pub fn by_id<V, S>(m: &HashMap<Id, V, S>) -> impl Iterator<Item=(&Id, &V)> {
+    let mut entries: Vec<_> = m.iter().collect();
+    entries.sort_unstable_by_key(|(e, _)| **e);
+    entries.into_iter()
+}
+
+#[doc(hidden)]
+pub fn by_id_mut<V, S>(m: &mut HashMap<Id, V, S>) -> impl Iterator<Item=(&Id, &mut V)> {
+    let mut entries: Vec<_> = m.iter_mut().collect();
+    entries.sort_unstable_by_key(|(e, _)| **e);
+    entries.into_iter()
+}
+
+// A single field of every component in a manager, see the columns method generated by component!
+// Columns of the same manager are independent of one another,
+// so one may be read while another is being written to
+pub struct Column<'a, F, S = RandomState>(&'a mut HashMap<Id, F, S>);
+
+impl<'a, F, S: std::hash::BuildHasher> Column<'a, F, S> {
+    #[doc(hidden)]
+    pub fn new(field: &'a mut HashMap<Id, F, S>) -> Self {
+        Self(field)
+    }
+
+    pub fn get(&self, e: Id) -> Option<&F> {
+        self.0.get(&e)
+    }
+
+    pub fn get_mut(&mut self, e: Id) -> Option<&mut F> {
+        self.0.get_mut(&e)
+    }
+
+    // In ascending Id order
+    pub fn iter(&self) -> impl Iterator<Item=(Id, &F)> {
+        by_id(self.0).map(|(e, f)| (*e, f))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(Id, &mut F)> {
+        by_id_mut(self.0).map(|(e, f)| (*e, f))
+    }
+}
+
+// Bookkeeping for component_dense! managers: which entity owns each index
+// Only the owner's generation may access the fields at its index, so a stale Id can't read a
+// component that (since then) belongs to a different entity
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DenseSlots {
+    owners: Vec<Option<Id>>,
+    len: usize,
+}
+
+impl DenseSlots {
+    pub fn with_capacity(n: usize) -> Self {
+        Self { owners: Vec::with_capacity(n), len: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.owners.capacity()
+    }
+
+    pub fn contains(&self, e: Id) -> bool {
+        self.owners.get(e.raw() as usize) == Some(&Some(e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Makes e the owner of its index, returning false if it already was
+    // (whatever previously owned the index, presumably a dead entity, loses it)
+    pub fn insert(&mut self, e: Id) -> bool {
+        let i = e.raw() as usize;
+        if self.owners.len() <= i {
+            self.owners.resize(i + 1, None);
+        }
+
+        match self.owners[i].replace(e) {
+            Some(owner) if owner == e => false,
+            Some(_) => true,
+            None => {
+                self.len += 1;
+                true
+            }
+        }
+    }
+
+    // Returns false if e didn't own its index
+    pub fn remove(&mut self, e: Id) -> bool {
+        if !self.contains(e) {
+            return false;
+        }
+
+        self.owners[e.raw() as usize] = None;
+        self.len -= 1;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.owners.clear();
+        self.len = 0;
+    }
+
+    // Drops the unowned indices past the last owned one, returning how many indices are left
+    // (the manager's fields can be cut down to that length)
+    pub fn shrink_to_fit(&mut self) -> usize {
+        let len = self.owners.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        self.owners.truncate(len);
+        self.owners.shrink_to_fit();
+        len
+    }
+
+    // In ascending Id order
+    pub fn ids(&self) -> impl Iterator<Item=Id> + '_ {
+        self.owners.iter().flatten().copied()
+    }
+}
+
+// Same as Column, for component_dense! managers
+pub struct DenseColumn<'a, F> {
+    values: &'a mut Vec<Option<F>>,
+    slots: &'a DenseSlots,
+}
+
+impl<'a, F> DenseColumn<'a, F> {
+    #[doc(hidden)]
+    pub fn new(values: &'a mut Vec<Option<F>>, slots: &'a DenseSlots) -> Self {
+        Self { values, slots }
+    }
+
+    pub fn get(&self, e: Id) -> Option<&F> {
+        if !self.slots.contains(e) {
+            return None;
+        }
+
+        self.values.get(e.raw() as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, e: Id) -> Option<&mut F> {
+        if !self.slots.contains(e) {
+            return None;
+        }
+
+        self.values.get_mut(e.raw() as usize)?.as_mut()
+    }
+
+    // In ascending Id order
+    pub fn iter(&self) -> impl Iterator<Item=(Id, &F)> {
+        self.slots.ids().zip(self.values.iter().flatten())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(Id, &mut F)> {
+        self.slots.ids().zip(self.values.iter_mut().flatten())
+    }
+}
+
+// Bookkeeping for component_sparse! managers: a sparse set mapping entities to rows
+// The owners of the rows (dense) are packed, the row of each entity index (sparse) is looked up
+// directly, and the manager's fields are Vecs lined up with the rows
+// New rows go at the end and removing one moves the last row into the gap, so neither shifts
+// anything, but both may leave the rows out of Id order (dirty)
+// rows (and ids) walk them in Id order regardless, sorting a list of rows on the side, and sort puts
+// the rows themselves back in order (for iter_mut, which can't jump around the fields)
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseSlots {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<Id>,
+    dirty: bool,
+}
+
+impl SparseSlots {
+    pub fn with_capacity(n: usize) -> Self {
+        Self { sparse: Vec::with_capacity(n), dense: Vec::with_capacity(n), dirty: false }
+    }
+
+    // Number of rows the fields can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.dense.capacity()
+    }
+
+    // e's row, if it owns one
+    pub fn row(&self, e: Id) -> Option<usize> {
+        let row = (*self.sparse.get(e.raw() as usize)?)?;
+        (self.dense[row] == e).then_some(row)
+    }
+
+    pub fn contains(&self, e: Id) -> bool {
+        self.row(e).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    // Ok with the row the fields already hold for e's index (whether e owned it, or a dead entity
+    // did and e takes it over), Err with the row that was made for e, the last one, to be pushed
+    // onto the fields
+    pub fn insert(&mut self, e: Id) -> Result<usize, usize> {
+        let i = e.raw() as usize;
+        if let Some(row) = self.sparse.get(i).copied().flatten() {
+            // same index as the previous owner, so the order holds
+            self.dense[row] = e;
+            return Ok(row);
+        }
+
+        if self.sparse.len() <= i {
+            self.sparse.resize(i + 1, None);
+        }
+
+        self.dirty |= self.dense.last().is_some_and(|last| *last > e);
+        let row = self.dense.len();
+        self.dense.push(e);
+        self.sparse[i] = Some(row);
+        Err(row)
+    }
+
+    // The row e gave up, to be swap_removed from the fields
+    pub fn remove(&mut self, e: Id) -> Option<usize> {
+        let row = self.row(e)?;
+        self.dense.swap_remove(row);
+        self.sparse[e.raw() as usize] = None;
+        if let Some(moved) = self.dense.get(row) {
+            self.sparse[moved.raw() as usize] = Some(row);
+            self.dirty = true;
+        }
+
+        Some(row)
+    }
+
+    pub fn clear(&mut self) {
+        self.sparse.clear();
+        self.dense.clear();
+        self.dirty = false;
+    }
+
+    // Drops the sparse entries past the last owned index
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.sparse.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        self.sparse.truncate(len);
+        self.sparse.shrink_to_fit();
+        self.dense.shrink_to_fit();
+    }
+
+    // Every owner along with its row, in ascending Id order
+    pub fn rows(&self) -> impl Iterator<Item=(Id, usize)> + '_ {
+        let rows = if self.dirty {
+            let mut rows: Vec<usize> = (0..self.dense.len()).collect();
+            rows.sort_unstable_by_key(|row| self.dense[*row]);
+            itertools::Either::Right(rows.into_iter())
+        } else {
+            itertools::Either::Left(0..self.dense.len())
+        };
+
+        rows.map(|row| (self.dense[row], row))
+    }
+
+    // In ascending Id order
+    pub fn ids(&self) -> impl Iterator<Item=Id> + '_ {
+        self.rows().map(|(e, _)| e)
+    }
+
+    // Puts the rows back in ascending Id order, handing back the swaps that did it,
+    // for the fields to go through as well
+    pub fn sort(&mut self) -> Vec<(usize, usize)> {
+        if !std::mem::take(&mut self.dirty) {
+            return Vec::new();
+        }
+
+        // row i is to take on the owner (and fields) of row order[i]
+        let mut order: Vec<usize> = (0..self.dense.len()).collect();
+        order.sort_unstable_by_key(|row| self.dense[*row]);
+
+        // each cycle of the permutation takes a swap per row but one
+        let mut swaps = Vec::new();
+        let mut placed = vec![false; order.len()];
+        for start in 0..order.len() {
+            let mut row = start;
+            while !placed[row] {
+                placed[row] = true;
+                if order[row] != start {
+                    swaps.push((row, order[row]));
+                }
+
+                row = order[row];
+            }
+        }
+
+        for (a, b) in &swaps {
+            self.dense.swap(*a, *b);
+        }
+
+        for (row, e) in self.dense.iter().enumerate() {
+            self.sparse[e.raw() as usize] = Some(row);
+        }
+
+        swaps
+    }
+}
+
+// Used by the query_mut! macro, not meant to be used directly
+// Walks a stream of (Id, T) pairs sorted by ascending Id (such as iter_mut_with_id),
+// handing out the T of any given Id, so long as the Ids are asked for in ascending order as well
+// This allows joining mutable iterators by Id, as get_mut can't hand out more than one RefMut at a time
+#[doc(hidden)]
+pub struct Seek<I: Iterator>(std::iter::Peekable<I>);
+
+impl<T, I: Iterator<Item=(Id, T)>> Seek<I> {
+    pub fn new(stream: impl IntoIterator<IntoIter=I>) -> Self {
+        Self(stream.into_iter().peekable())
+    }
+
+    pub fn seek(&mut self, e: Id) -> Option<T> {
+        while self.0.next_if(|(id, _)| *id < e).is_some() {}
+        self.0.next_if(|(id, _)| *id == e).map(|(_, t)| t)
+    }
+}
+
+// internal, derives Serialize and Deserialize for the managers generated by component! and friends
+// if this crate's serde feature is on (which the macros can't check themselves, as they expand in
+// the crate using them, whose features are its own)
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! serde_manager {
+    ($manager:item) => {
+        #[derive($crate::serde::Serialize, $crate::serde::Deserialize)]
+        $manager
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! serde_manager {
+    ($manager:item) => {
+        $manager
+    };
+}
+
+// internal, same idea as serde_manager!, keeps the methods it wraps only if this crate's rayon feature is on
+#[cfg(feature = "rayon")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! rayon_methods {
+    ($($methods:tt)*) => {
+        $($methods)*
+    };
+}
+
+#[cfg(not(feature = "rayon"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! rayon_methods {
+    ($($methods:tt)*) => {};
+}
+
+// Define your component struct inside the macro to automatically
+// implement the Component trait (and required Ref glue) for it
+// Caveats:
+// The struct must be default-derivable (unless marked #[no_default], see below)
+// Fields are wired up in declaration order, so the #[slot(n)] annotations are optional
+// (struct Foo { x: i32, y: i32 } is the same as struct Foo { #[slot(0)] x: i32, #[slot(1)] y: i32 })
+// but those that are given must match the field's position, a duplicate or skipped slot fails to compile
+// The struct's visibility (pub struct Foo) carries over to the generated Ref, RefMut and Manager types
+// Field visibility (struct Foo { pub x: () }) is honored on the struct, fields are private otherwise
+// like in any other struct, though the Ref and RefMut glue always exposes every field
+// Does not work with generics/lifetimes (struct<T> Foo { ... })
+// Doess not work with tuple structs (struct Foo(...))
+// Attributes on the struct (#[derive(Clone, Debug)] for example) are forwarded to it,
+// except for Default, which is always derived (and so must not be derived again)
+//
+// Components whose fields have no sensible default (NonZeroU32, handles to external resources...)
+// can opt out of the derive by putting #[no_default] first among the struct's attributes
+// Adding one with add(e, None) then panics, so pass the component itself (add(e, Some(c)) or set)
+// The same goes for component_dense!, component_sparse!, component_fast! and component_multi!, but not for archetype!
+// members, as archetype rows are padded with default components
+// No field may be named `hooks` (the generated manager stores its observers there)
+//
+// The generated managers' observers may be any closure (see Observers), which keeps the managers
+// on their own thread, a manager to be handed to a SyncEntityManager only takes Send ones instead,
+// and is itself Send (and Sync), by putting #[sync] first among the struct's attributes
+// (before #[no_default], if both are given), the same goes for every other component macro
+// HashMaps iterate in an order that changes from run to run, so iter and iter_mut sort the entries
+// by Id every time (see by_id), trading some speed for determinism
+// component_dense! avoids the sort, its storage already is in Id order
+// component_fast! is the same as component!, hashing Ids with a cheaper hasher
+//
+// With the serde feature on, the generated managers implement Serialize and Deserialize, for saving
+// and loading a world: the storage is saved as it is, each field on its own (observers aren't saved, a loaded
+// manager has none), so every field's type has to implement them too, as do the components of
+// an archetype!, and the crate using the macros has to depend on serde itself
+//
+// A struct without any fields (struct Player {}) makes a tag component, one that only marks entities
+// It becomes a unit struct, its Ref and RefMut are () and its manager is a HashSet<Id>,
+// use contains to check an entity for the tag, or query! to visit every tagged entity
+/// ```compile_fail
+/// use ecs::*;
+///
+/// component! {
+///     struct Gapped {
+///         #[slot(0)] x: i32,
+///         #[slot(2)] y: i32,
+///     }
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use ecs::*;
+///
+/// component! {
+///     struct Duplicated {
+///         #[slot(0)] x: i32,
+///         #[slot(0)] y: i32,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! component {
+    // internal, expands to the first field of the component
+    // (the field maps are kept in sync, so any single one can stand in for the whole component)
+    (@first_field $this:ident, $first:ident $(, $rest:ident)*) => {
+        $this.$first
+    };
+
+    // internal, the slot of a field, if annotated with one
+    (@slot $s:literal) => {
+        Some($s)
+    };
+
+    (@slot) => {
+        None
+    };
+
+    // internal, fails to compile unless every #[slot(n)] matches its field's position
+    (@check_slots $name:ident [$( ($($s:literal)?) )+]) => {
+        const _: () = assert!(
+            $crate::slots_in_order(&[ $( $crate::component!(@slot $($s)?) ),+ ]),
+            concat!("the #[slot(n)] annotations of ", stringify!($name), " must number its fields 0, 1, 2... in declaration order"),
+        );
+    };
+
+    // internal, the component struct, deriving Default unless marked #[no_default]
+    (@struct default $(#[$attr:meta])* $vis:vis $name:ident { $( $field_vis:vis $field_name:ident: $field_type:ty, )+ }) => {
+        #[derive(Default)]
+        $(#[$attr])*
+        $vis struct $name {
+        $( $field_vis $field_name: $field_type ),+
+        }
+    };
+
+    (@struct no_default $(#[$attr:meta])* $vis:vis $name:ident { $( $field_vis:vis $field_name:ident: $field_type:ty, )+ }) => {
+        $(#[$attr])*
+        $vis struct $name {
+        $( $field_vis $field_name: $field_type ),+
+        }
+    };
+
+    // internal, the component given to add, falling back on the default (if there is one)
+    (@or_default default $name:ident $c:expr) => {
+        $c.unwrap_or_default()
+    };
+
+    (@or_default no_default $name:ident $c:expr) => {
+        $c.unwrap_or_else(|| panic!(concat!(stringify!($name), " has no default, add it with Some(component)")))
+    };
+
+    // internal, the component struct and its glue, shared by every storage backend
+    (@types [$default:ident] $(#[$attr:meta])* $vis:vis $name:ident { $( $field_vis:vis $field_name:ident: $field_type:ty, )+ }) => {
+        $crate::component!(@struct $default $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+
+        $crate::paste::paste! {
+        // create Ref/RefMut glue
+        // (their fields are always pub, iterating a component must give access to all of it)
+        $vis struct [< $name Ref >]<'a> {
+        $( pub $field_name: &'a $field_type ),+
+        }
+
+        $vis struct [< $name RefMut >]<'a> {
+        $( pub $field_name: &'a mut $field_type ),+
+        }
+
+        // implement From(tuple) for glue types (iterator support)
+        // the tuple is destructured by position, so its fields go in declaration order
+        // (a single field is no tuple at all, as izip! yields it bare, hence the allows)
+        #[allow(unused_parens)]
+        impl<'a> From<( $(&'a $field_type),+ )> for [< $name Ref >]<'a> {
+            fn from(value: ( $(&'a $field_type),+ )) -> Self {
+                let ( $( $field_name ),+ ) = value;
+                Self { $( $field_name ),+ }
+            }
+        }
+
+        #[allow(unused_parens)]
+        impl<'a> From<( $(&'a mut $field_type),+ )> for [< $name RefMut >]<'a> {
+            fn from(value: ( $(&'a mut $field_type),+ )) -> Self {
+                let ( $( $field_name ),+ ) = value;
+                Self { $( $field_name ),+ }
+            }
+        }
+
+        // same as above, with every field tagged with the Id it was found under,
+        // rejecting fields that don't all belong to the same entity
+        // (joining the field maps by position quietly relies on them all holding the same Ids)
+        #[allow(unused_parens)]
+        impl<'a> TryFrom<( $(($crate::Id, &'a $field_type)),+ )> for [< $name Ref >]<'a> {
+            type Error = $crate::AssembleError;
+
+            fn try_from(value: ( $(($crate::Id, &'a $field_type)),+ )) -> Result<Self, Self::Error> {
+                let ( $( ([< $field_name _id >], $field_name) ),+ ) = value;
+                let ids = [ $( [< $field_name _id >] ),+ ];
+                match ids.iter().find(|e| **e != ids[0]) {
+                    Some(e) => Err($crate::AssembleError::Mismatched(ids[0], *e)),
+                    None => Ok(Self { $( $field_name ),+ }),
+                }
+            }
+        }
+
+        #[allow(unused_parens)]
+        impl<'a> TryFrom<( $(($crate::Id, &'a mut $field_type)),+ )> for [< $name RefMut >]<'a> {
+            type Error = $crate::AssembleError;
+
+            fn try_from(value: ( $(($crate::Id, &'a mut $field_type)),+ )) -> Result<Self, Self::Error> {
+                let ( $( ([< $field_name _id >], $field_name) ),+ ) = value;
+                let ids = [ $( [< $field_name _id >] ),+ ];
+                match ids.iter().find(|e| **e != ids[0]) {
+                    Some(e) => Err($crate::AssembleError::Mismatched(ids[0], *e)),
+                    None => Ok(Self { $( $field_name ),+ }),
+                }
+            }
+        }
+
+        // cloning a component back out of its Ref, for components whose fields are all Clone
+        // (the for<'x> defers the bounds to the impl's use site, otherwise a component with a field
+        // that isn't Clone would fail to compile instead of simply going without this impl)
+        impl<'a> From<[< $name Ref >]<'a>> for $name where $( for<'x> $field_type: Clone ),+ {
+            fn from(value: [< $name Ref >]<'a>) -> Self {
+                Self { $( $field_name: Clone::clone(value.$field_name) ),+ }
+            }
+        }
+
+        // Debug for the glue, whenever every field is Debug (see the From impl above for the for<'x>)
+        // The struct itself doesn't get one, #[derive(Debug)] on it is forwarded as usual
+        impl<'a> std::fmt::Debug for [< $name Ref >]<'a> where $( for<'x> $field_type: std::fmt::Debug ),+ {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!([< $name Ref >]))
+                $( .field(stringify!($field_name), self.$field_name) )+
+                .finish()
+            }
+        }
+
+        impl<'a> std::fmt::Debug for [< $name RefMut >]<'a> where $( for<'x> $field_type: std::fmt::Debug ),+ {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!([< $name RefMut >]))
+                $( .field(stringify!($field_name), self.$field_name) )+
+                .finish()
+            }
+        }
+
+        // implement Component
+        impl $crate::Component for $name {
+            type Ref<'a> = [< $name Ref >]<'a>;
+            type RefMut<'a> = [< $name RefMut >]<'a>;
+        }
+
+        impl $crate::ComponentFields for $name {
+            fn fields(&self) -> [< $name Ref >]<'_> {
+                From::from((
+                $( &self.$field_name ),+
+                ))
+            }
+
+            fn fields_mut(&mut self) -> [< $name RefMut >]<'_> {
+                From::from((
+                $( &mut self.$field_name ),+
+                ))
+            }
+        }
+        }
+    };
+
+    // internal, lets managers be looped over directly (for t in &transforms { ... })
+    // iter's type can't be named, so the iterator is boxed, call iter/iter_mut to avoid that
+    (@into_iter $name:ident) => {
+        $crate::paste::paste! {
+        impl<'a> IntoIterator for &'a [< $name Manager >] {
+            type Item = <$name as $crate::Component>::Ref<'a>;
+            type IntoIter = Box<dyn Iterator<Item=Self::Item> + 'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                Box::new($crate::ComponentManager::<$name>::iter(self))
+            }
+        }
+
+        impl<'a> IntoIterator for &'a mut [< $name Manager >] {
+            type Item = <$name as $crate::Component>::RefMut<'a>;
+            type IntoIter = Box<dyn Iterator<Item=Self::Item> + 'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                Box::new($crate::ComponentManager::<$name>::iter_mut(self))
+            }
+        }
+        }
+    };
+
+    // internal, Debug for a manager, printing each entity alongside its component
+    // (goes through the ComponentManager impl, so it fits every storage backend)
+    (@debug_manager $name:ident { $( $field_type:ty ),+ }) => {
+        $crate::paste::paste! {
+        impl std::fmt::Debug for [< $name Manager >] where $( for<'x> $field_type: std::fmt::Debug ),+ {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_map().entries($crate::ComponentManager::<$name>::iter_with_id(self)).finish()
+            }
+        }
+        }
+    };
+
+    // tag components, see above
+    (@observers [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident {}) => {
+        #[derive(Default)]
+        $(#[$attr])*
+        $vis struct $name;
+
+        $crate::component!(@into_iter $name);
+
+        impl $crate::Component for $name {
+            type Ref<'a> = ();
+            type RefMut<'a> = ();
+        }
+
+        impl $crate::ComponentFields for $name {
+            fn fields(&self) {}
+            fn fields_mut(&mut self) {}
+        }
+
+        // a tag is as Clone as it gets
+        impl From<()> for $name {
+            fn from(_: ()) -> Self {
+                Self
+            }
+        }
+
+        $crate::paste::paste! {
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $vis struct [< $name Manager >] {
+            tagged: std::collections::HashSet<$crate::Id>,
+            hooks: $crate::ComponentHooks<$crate::$observers>,
+        }
+        }
+
+        impl [< $name Manager >] {
+            $vis fn with_capacity(n: usize) -> Self {
+                Self { tagged: std::collections::HashSet::with_capacity(n), hooks: Default::default() }
+            }
+
+            $vis fn capacity(&self) -> usize {
+                self.tagged.capacity()
+            }
+        }
+
+        impl std::fmt::Debug for [< $name Manager >] {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_set().entries($crate::ComponentManager::<$name>::ids(self)).finish()
+            }
+        }
+
+        impl $crate::Manages for [< $name Manager >] {
+            type Component = $name;
+        }
+
+        impl $crate::ComponentManager<$name> for [< $name Manager >] {
+            fn add(&mut self, e: $crate::Id, _: Option<$name>) {
+                $crate::ComponentManager::set(self, e, $name);
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<$name> {
+                if !self.tagged.remove(&e) {
+                    return None;
+                }
+
+                self.hooks.removed(e);
+                Some($name)
+            }
+
+            fn set(&mut self, e: $crate::Id, _: $name) -> Option<$name> {
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                if !self.tagged.insert(e) {
+                    return Some($name);
+                }
+
+                self.hooks.added(e);
+                None
+            }
+
+            // tags are all alike, only moving one over changes anything
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                if self.tagged.contains(&a) != self.tagged.contains(&b) {
+                    $crate::swap_by_remove(self, a, b);
+                }
+            }
+
+            fn clear(&mut self) {
+                let ids: Vec<$crate::Id> = $crate::ComponentManager::ids(self).collect();
+                self.tagged.clear();
+
+                for e in ids {
+                    self.hooks.removed(e);
+                }
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                self.tagged.contains(&e)
+            }
+
+            fn len(&self) -> usize {
+                self.tagged.len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<()> {
+                self.tagged.contains(&e).then_some(())
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<()> {
+                self.tagged.contains(&e).then_some(())
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                let mut ids: Vec<$crate::Id> = self.tagged.iter().copied().collect();
+                ids.sort_unstable();
+                ids.into_iter()
+            }
+
+            fn iter(&self) -> impl Iterator<Item=()> {
+                self.tagged.iter().map(|_| ())
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=()> {
+                self.tagged.iter().map(|_| ())
+            }
+
+            fn shrink_to_fit(&mut self) {
+                self.tagged.shrink_to_fit();
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_remove(f);
+            }
+        }
+        }
+    };
+
+    // internal, the HashMap backed manager, hashing Ids with $hasher (see component_fast!)
+    (@hash_map [$hasher:ty] [$default:ident] [$observers:ident] $(#[$attr:meta])* $vis:vis $name:ident { $( $field_vis:vis $field_name:ident: $field_type:ty, )+ }) => {
+        $crate::component!(@types [$default] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+        $crate::component!(@debug_manager $name { $( $field_type ),+ });
+        $crate::component!(@into_iter $name);
+
+        $crate::paste::paste! {
+        // implement SoA
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $vis struct [< $name Manager >] {
+        $( $field_name: std::collections::HashMap<$crate::Id, $field_type, $hasher>, )+
+        hooks: $crate::ComponentHooks<$crate::$observers>,
+        }
+        }
+
+        impl [< $name Manager >] {
+            // Preallocates every field map for n components, so the first n adds don't rehash
+            $vis fn with_capacity(n: usize) -> Self {
+                Self {
+                    $( $field_name: std::collections::HashMap::with_capacity_and_hasher(n, Default::default()), )+
+                    hooks: Default::default(),
+                }
+            }
+
+            // Number of components the manager can hold without reallocating
+            // (the field maps grow in lockstep, but the largest one is what's allocated)
+            // Compared with len, this is the manager's load factor, for spotting over-allocation
+            $vis fn capacity(&self) -> usize {
+                0 $( .max(self.$field_name.capacity()) )+
+            }
+
+            // Splits the manager into its fields, for passes that read some fields and write others
+            // let mut columns = transforms.columns();
+            // for (e, x) in columns.x.iter() {
+            //     *columns.y.get_mut(e).unwrap() += *x;
+            // }
+            $vis fn columns(&mut self) -> [< $name Columns >]<'_> {
+                [< $name Columns >] {
+                    $( $field_name: $crate::Column::new(&mut self.$field_name), )+
+                }
+            }
+
+            // Read-only access to each field's map (x_map, y_map, ...), for whatever the trait doesn't cover
+            // These are as visible as the fields themselves
+            $(
+            $field_vis fn [< $field_name _map >](&self) -> &std::collections::HashMap<$crate::Id, $field_type, $hasher> {
+                &self.$field_name
+            }
+            )+
+
+            // Drops every entity found in some of the field maps but not all of them, returning how many
+            // there were
+            // The field maps are meant to hold the same Ids at all times, but should they fall out of step
+            // (through a bug, or a panic in the middle of an add), iter panics rather than mixing up
+            // entities, so this is how to get back on track
+            $vis fn repair(&mut self) -> usize {
+                let mut ids: Vec<$crate::Id> = Vec::new();
+                $( ids.extend(self.$field_name.keys().copied()); )+
+                ids.sort_unstable();
+                ids.dedup();
+
+                let broken: Vec<$crate::Id> = ids.into_iter().filter(|e| !(true $( && self.$field_name.contains_key(e) )+)).collect();
+                for e in &broken {
+                    $crate::ComponentManager::remove(self, *e);
+                }
+
+                broken.len()
+            }
+
+            // Runs f on one field of every component (for_each_x, for_each_y, ...), in ascending Id order
+            // For bulk updates to a single field, only that field's map is walked
+            $(
+            $field_vis fn [< for_each_ $field_name >](&mut self, f: impl FnMut(&mut $field_type)) {
+                $crate::by_id_mut(&mut self.$field_name).map(|(_, v)| v).for_each(f);
+            }
+            )+
+
+            // Same as iter_with_id, skipping the components whose field doesn't match pred (iter_where_x, ...)
+            // for (e, b) in buttons.iter_where_enabled(|enabled| *enabled) { ... }
+            $(
+            $field_vis fn [< iter_where_ $field_name >](&self, mut pred: impl FnMut(&$field_type) -> bool) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                $crate::ComponentManager::iter_with_id(self).filter(move |(_, c)| pred(c.$field_name))
+            }
+            )+
+        }
+
+        $vis struct [< $name Columns >]<'a> {
+        $( pub $field_name: $crate::Column<'a, $field_type, $hasher>, )+
+        }
+
+        // implement ComponentManager for SoA
+        impl $crate::Manages for [< $name Manager >] {
+            type Component = $name;
+        }
+
+        impl $crate::ComponentManager<$name> for [< $name Manager >] {
+            fn add(&mut self, e: $crate::Id, default: Option<$name>) {
+                if $crate::rejects_null(e) {
+                    return;
+                }
+
+                let default = $crate::component!(@or_default $default $name default);
+                $(
+                self.$field_name.insert(e, default.$field_name);
+                )+
+                self.hooks.added(e);
+            }
+
+            // every field map is cleaned, even those left holding e when others don't (see repair)
+            fn remove(&mut self, e: $crate::Id) -> Option<$name> {
+                let had = $crate::ComponentManager::contains(self, e);
+                $(
+                let $field_name = self.$field_name.remove(&e);
+                )+
+
+                if had {
+                    self.hooks.removed(e);
+                }
+
+                Some($name {
+                    $( $field_name: $field_name?, )+
+                })
+            }
+
+            fn set(&mut self, e: $crate::Id, c: $name) -> Option<$name> {
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                let had = $crate::ComponentManager::contains(self, e);
+                $(
+                let $field_name = self.$field_name.insert(e, c.$field_name);
+                )+
+
+                if !had {
+                    self.hooks.added(e);
+                    return None;
+                }
+
+                Some($name {
+                    $( $field_name: $field_name?, )+
+                })
+            }
+
+            fn clear(&mut self) {
+                let ids: Vec<$crate::Id> = $crate::ComponentManager::ids(self).collect();
+                $(
+                self.$field_name.clear();
+                )+
+
+                for e in ids {
+                    self.hooks.removed(e);
+                }
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                $crate::component!(@first_field self, $( $field_name ),+).contains_key(&e)
+            }
+
+            fn len(&self) -> usize {
+                $crate::component!(@first_field self, $( $field_name ),+).len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<<$name as $crate::Component>::Ref<'_>> {
+                Some(From::from((
+                $( self.$field_name.get(&e)? ),+
+                )))
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<<$name as $crate::Component>::RefMut<'_>> {
+                Some(From::from((
+                $( self.$field_name.get_mut(&e)? ),+
+                )))
+            }
+
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                if a == b || !($crate::ComponentManager::contains(self, a) && $crate::ComponentManager::contains(self, b)) {
+                    return $crate::swap_by_remove(self, a, b);
+                }
+
+                $(
+                let x = self.$field_name.remove(&a).unwrap();
+                let y = self.$field_name.insert(b, x).unwrap();
+                self.$field_name.insert(a, y);
+                )+
+            }
+
+            fn get_many_mut<const N: usize>(&mut self, ids: [$crate::Id; N]) -> Option<[<$name as $crate::Component>::RefMut<'_>; N]> {
+                if !$crate::disjoint(&ids) || !ids.iter().all(|e| self.contains(*e)) {
+                    return None;
+                }
+
+                $( let mut $field_name = self.$field_name.get_disjoint_mut(ids.each_ref()).into_iter().flatten(); )+
+                Some(std::array::from_fn(|_| From::from((
+                $( $field_name.next().unwrap() ),+
+                ))))
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                $crate::by_id(&$crate::component!(@first_field self, $( $field_name ),+)).map(|(e, _)| *e)
+            }
+
+            // the fields are paired up by position, should the field maps ever fall out of sync
+            // this panics instead of handing out a component stitched together from several entities
+            fn iter(&self) -> impl Iterator<Item=<$name as $crate::Component>::Ref<'_>>{
+                $crate::itertools::izip! {
+                $( $crate::by_id(&self.$field_name).map(|(e, f)| (*e, f)) ),+
+                }
+                .map(|fields| TryFrom::try_from(fields).unwrap_or_else(|e| panic!("{e}")))
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=<$name as $crate::Component>::RefMut<'_>>{
+                $crate::itertools::izip! {
+                $( $crate::by_id_mut(&mut self.$field_name).map(|(e, f)| (*e, f)) ),+
+                }
+                .map(|fields| TryFrom::try_from(fields).unwrap_or_else(|e| panic!("{e}")))
+            }
+
+            fn shrink_to_fit(&mut self) {
+                $( self.$field_name.shrink_to_fit(); )+
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_remove(f);
+            }
+        }
+        }
+    };
+
+    (@observers [$observers:ident] #[no_default] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@hash_map [std::collections::hash_map::RandomState] [no_default] [$observers] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+    };
+
+    (@observers [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@hash_map [std::collections::hash_map::RandomState] [default] [$observers] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::component!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::component!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+// Same as component!, but the generated manager hashes Ids with IdHasher instead of the
+// standard library's SipHash, which is built to resist collision attacks rather than for speed
+// Ids are handed out by the entity manager, not by whoever might be attacking, so
+// there's little to resist, and integer keys hash a lot faster without it
+// Tag components ({}) are not supported, use component! for those
+#[macro_export]
+macro_rules! component_fast {
+    (@observers [$observers:ident] #[no_default] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@hash_map [$crate::BuildIdHasher] [no_default] [$observers] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+    };
+
+    (@observers [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@hash_map [$crate::BuildIdHasher] [default] [$observers] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::component_fast!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::component_fast!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+// Same as component!, but the generated manager stores each field in a Vec<Option<F>> indexed by
+// the entity's index (EntityId::raw) instead of a HashMap<Id, F>
+// This suits components that nearly every entity has: there's no hashing, iteration walks the
+// Vecs front to back (which is ascending Id order), but the Vecs are as long as the highest index seen
+// On top of component!'s caveats, no field may be named `slots`
+#[macro_export]
+macro_rules! component_dense {
+    // internal, the whole manager, see component!'s #[no_default]
+    (@manager [$default:ident] [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@types [$default] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+        $crate::component!(@debug_manager $name { $( $field_type ),+ });
+        $crate::component!(@into_iter $name);
+
+        $crate::paste::paste! {
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $vis struct [< $name Manager >] {
+        $( $field_name: Vec<Option<$field_type>>, )+
+        slots: $crate::DenseSlots,
+        hooks: $crate::ComponentHooks<$crate::$observers>,
+        }
+        }
+
+        impl [< $name Manager >] {
+            // Preallocates every field for entity indices below n
+            $vis fn with_capacity(n: usize) -> Self {
+                Self {
+                    $( $field_name: Vec::with_capacity(n), )+
+                    slots: $crate::DenseSlots::with_capacity(n),
+                    hooks: Default::default(),
+                }
+            }
+
+            // Highest entity index (exclusive) the manager can hold without reallocating
+            $vis fn capacity(&self) -> usize {
+                self.slots.capacity()
+            }
+
+            // See component!
+            $vis fn columns(&mut self) -> [< $name Columns >]<'_> {
+                [< $name Columns >] {
+                    $( $field_name: $crate::DenseColumn::new(&mut self.$field_name, &self.slots), )+
+                }
+            }
+
+            $crate::rayon_methods! {
+            // Same as iter_mut, but as a rayon ParallelIterator (needs the rayon feature)
+            // The fields' Vecs are zipped index by index, so each item is a whole component
+            // (this is why it's dense only: the Vecs can be split into disjoint mutable chunks)
+            // Requires every field to be Send, and visits the components in no particular order
+            $vis fn par_iter_mut(&mut self) -> impl $crate::rayon::iter::ParallelIterator<Item=<$name as $crate::Component>::RefMut<'_>>
+            where
+                $( for<'x> $field_type: Send ),+
+            {
+                use $crate::rayon::iter::{IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+                ( $( self.$field_name.par_iter_mut(), )+ )
+                    .into_par_iter()
+                    .filter_map(|( $( $field_name, )+ )| Some(From::from(( $( $field_name.as_mut()? ),+ ))))
+            }
+
+            // Shorthand for par_iter_mut().for_each(f)
+            $vis fn par_for_each_mut(&mut self, f: impl Fn(<$name as $crate::Component>::RefMut<'_>) + Sync + Send)
+            where
+                $( for<'x> $field_type: Send ),+
+            {
+                $crate::rayon::iter::ParallelIterator::for_each(self.par_iter_mut(), f);
+            }
+            }
+
+            // Runs f on one field of every component (for_each_x, for_each_y, ...), in ascending Id order
+            // For bulk updates to a single field, only that field's Vec is walked
+            $(
+            $field_vis fn [< for_each_ $field_name >](&mut self, f: impl FnMut(&mut $field_type)) {
+                self.$field_name.iter_mut().flatten().for_each(f);
+            }
+            )+
+
+            // See component!
+            $(
+            $field_vis fn [< iter_where_ $field_name >](&self, mut pred: impl FnMut(&$field_type) -> bool) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                $crate::ComponentManager::iter_with_id(self).filter(move |(_, c)| pred(c.$field_name))
+            }
+            )+
+        }
+
+        $vis struct [< $name Columns >]<'a> {
+        $( pub $field_name: $crate::DenseColumn<'a, $field_type>, )+
+        }
+
+        impl $crate::Manages for [< $name Manager >] {
+            type Component = $name;
+        }
+
+        impl $crate::ComponentManager<$name> for [< $name Manager >] {
+            fn add(&mut self, e: $crate::Id, default: Option<$name>) {
+                $crate::ComponentManager::set(self, e, $crate::component!(@or_default $default $name default));
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<$name> {
+                if !self.slots.remove(e) {
+                    return None;
+                }
+
+                let i = e.raw() as usize;
+                $(
+                let $field_name = self.$field_name[i].take();
+                )+
+                self.hooks.removed(e);
+
+                Some($name {
+                    $( $field_name: $field_name?, )+
+                })
+            }
+
+            fn set(&mut self, e: $crate::Id, c: $name) -> Option<$name> {
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                let had = !self.slots.insert(e);
+
+                let i = e.raw() as usize;
+                $(
+                if self.$field_name.len() <= i {
+                    self.$field_name.resize_with(i + 1, || None);
+                }
+                let $field_name = self.$field_name[i].replace(c.$field_name);
+                )+
+
+                if !had {
+                    self.hooks.added(e);
+                    return None;
+                }
+
+                Some($name {
+                    $( $field_name: $field_name?, )+
+                })
+            }
+
+            fn clear(&mut self) {
+                let ids: Vec<$crate::Id> = self.slots.ids().collect();
+                $(
+                self.$field_name.clear();
+                )+
+                self.slots.clear();
+
+                for e in ids {
+                    self.hooks.removed(e);
+                }
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                self.slots.contains(e)
+            }
+
+            fn len(&self) -> usize {
+                self.slots.len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<<$name as $crate::Component>::Ref<'_>> {
+                if !self.slots.contains(e) {
+                    return None;
+                }
+
+                let i = e.raw() as usize;
+                Some(From::from((
+                $( self.$field_name.get(i)?.as_ref()? ),+
+                )))
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<<$name as $crate::Component>::RefMut<'_>> {
+                if !self.slots.contains(e) {
+                    return None;
+                }
+
+                let i = e.raw() as usize;
+                Some(From::from((
+                $( self.$field_name.get_mut(i)?.as_mut()? ),+
+                )))
+            }
+
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                if a == b || !($crate::ComponentManager::contains(self, a) && $crate::ComponentManager::contains(self, b)) {
+                    return $crate::swap_by_remove(self, a, b);
+                }
+
+                $( self.$field_name.swap(a.raw() as usize, b.raw() as usize); )+
+            }
+
+            fn get_many_mut<const N: usize>(&mut self, ids: [$crate::Id; N]) -> Option<[<$name as $crate::Component>::RefMut<'_>; N]> {
+                if !$crate::disjoint(&ids) || !ids.iter().all(|e| self.slots.contains(*e)) {
+                    return None;
+                }
+
+                let indices = ids.map(|e| e.raw() as usize);
+                $( let mut $field_name = self.$field_name.get_disjoint_mut(indices).ok()?.into_iter().flat_map(Option::as_mut); )+
+                Some(std::array::from_fn(|_| From::from((
+                $( $field_name.next().unwrap() ),+
+                ))))
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                self.slots.ids()
+            }
+
+            fn iter(&self) -> impl Iterator<Item=<$name as $crate::Component>::Ref<'_>>{
+                $crate::itertools::izip! {
+                $( self.$field_name.iter().flatten() ),+
+                }
+                .map(From::from)
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=<$name as $crate::Component>::RefMut<'_>>{
+                $crate::itertools::izip! {
+                $( self.$field_name.iter_mut().flatten() ),+
+                }
+                .map(From::from)
+            }
+
+            fn shrink_to_fit(&mut self) {
+                let len = self.slots.shrink_to_fit();
+                $(
+                self.$field_name.truncate(len);
+                self.$field_name.shrink_to_fit();
+                )+
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_remove(f);
+            }
+        }
+        }
+    };
+
+    (@observers [$observers:ident] #[no_default] $($rest:tt)+) => {
+        $crate::component_dense!(@manager [no_default] [$observers] $($rest)+);
+    };
+
+    (@observers [$observers:ident] $($rest:tt)+) => {
+        $crate::component_dense!(@manager [default] [$observers] $($rest)+);
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::component_dense!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::component_dense!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+// Same as component!, but the generated manager stores each field as a sparse set (see SparseSlots):
+// the values are packed into Vecs, one row per entity, and found through an index by entity index
+// This suits components that come and go (add, remove and get take constant time, no hashing)
+// and are iterated often (iter_mut and for_each_ walk the packed Vecs front to back, after sorting
+// the rows back into ascending Id order if adds or removes moved them out of it)
+// On top of component!'s caveats, no field may be named `slots`
+#[macro_export]
+macro_rules! component_sparse {
+    // internal, the whole manager, see component!'s #[no_default]
+    (@manager [$default:ident] [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@types [$default] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+        $crate::component!(@debug_manager $name { $( $field_type ),+ });
+        $crate::component!(@into_iter $name);
+
+        $crate::paste::paste! {
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $vis struct [< $name Manager >] {
+        $( $field_name: Vec<$field_type>, )+
+        slots: $crate::SparseSlots,
+        hooks: $crate::ComponentHooks<$crate::$observers>,
+        }
+        }
+
+        impl [< $name Manager >] {
+            // Preallocates every field for n components
+            $vis fn with_capacity(n: usize) -> Self {
+                Self {
+                    $( $field_name: Vec::with_capacity(n), )+
+                    slots: $crate::SparseSlots::with_capacity(n),
+                    hooks: Default::default(),
+                }
+            }
+
+            // Number of components the manager can hold without reallocating
+            $vis fn capacity(&self) -> usize {
+                self.slots.capacity()
+            }
+
+            // See component_dense!
+            $(
+            $field_vis fn [< for_each_ $field_name >](&mut self, f: impl FnMut(&mut $field_type)) {
+                self.sort_rows();
+                self.$field_name.iter_mut().for_each(f);
+            }
+            )+
+
+            // See component!
+            $(
+            $field_vis fn [< iter_where_ $field_name >](&self, mut pred: impl FnMut(&$field_type) -> bool) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                $crate::ComponentManager::iter_with_id(self).filter(move |(_, c)| pred(c.$field_name))
+            }
+            )+
+
+            // Puts the rows back in Id order, see SparseSlots
+            fn sort_rows(&mut self) {
+                for (a, b) in self.slots.sort() {
+                    $( self.$field_name.swap(a, b); )+
+                }
+            }
+        }
+
+        impl $crate::Manages for [< $name Manager >] {
+            type Component = $name;
+        }
+
+        impl $crate::ComponentManager<$name> for [< $name Manager >] {
+            fn add(&mut self, e: $crate::Id, default: Option<$name>) {
+                $crate::ComponentManager::set(self, e, $crate::component!(@or_default $default $name default));
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<$name> {
+                let row = self.slots.remove(e)?;
+                $(
+                let $field_name = self.$field_name.swap_remove(row);
+                )+
+                self.hooks.removed(e);
+
+                Some($name {
+                    $( $field_name, )+
+                })
+            }
+
+            fn set(&mut self, e: $crate::Id, c: $name) -> Option<$name> {
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                let had = self.slots.contains(e);
+                let row = match self.slots.insert(e) {
+                    Ok(row) => row,
+                    Err(_) => {
+                        $( self.$field_name.push(c.$field_name); )+
+                        self.hooks.added(e);
+                        return None;
+                    }
+                };
+
+                $(
+                let $field_name = std::mem::replace(&mut self.$field_name[row], c.$field_name);
+                )+
+
+                // the row belonged to a dead entity, which takes its component along
+                if !had {
+                    self.hooks.added(e);
+                    return None;
+                }
+
+                Some($name {
+                    $( $field_name, )+
+                })
+            }
+
+            fn clear(&mut self) {
+                let ids: Vec<$crate::Id> = self.slots.ids().collect();
+                $(
+                self.$field_name.clear();
+                )+
+                self.slots.clear();
+
+                for e in ids {
+                    self.hooks.removed(e);
+                }
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                self.slots.contains(e)
+            }
+
+            fn len(&self) -> usize {
+                self.slots.len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<<$name as $crate::Component>::Ref<'_>> {
+                let row = self.slots.row(e)?;
+                Some(From::from((
+                $( &self.$field_name[row] ),+
+                )))
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<<$name as $crate::Component>::RefMut<'_>> {
+                let row = self.slots.row(e)?;
+                Some(From::from((
+                $( &mut self.$field_name[row] ),+
+                )))
+            }
+
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                if a == b || !($crate::ComponentManager::contains(self, a) && $crate::ComponentManager::contains(self, b)) {
+                    return $crate::swap_by_remove(self, a, b);
+                }
+
+                let (a, b) = (self.slots.row(a).unwrap(), self.slots.row(b).unwrap());
+                $( self.$field_name.swap(a, b); )+
+            }
+
+            fn get_many_mut<const N: usize>(&mut self, ids: [$crate::Id; N]) -> Option<[<$name as $crate::Component>::RefMut<'_>; N]> {
+                if !$crate::disjoint(&ids) {
+                    return None;
+                }
+
+                let mut rows = [0; N];
+                for (row, e) in rows.iter_mut().zip(ids) {
+                    *row = self.slots.row(e)?;
+                }
+
+                $( let mut $field_name = self.$field_name.get_disjoint_mut(rows).ok()?.into_iter(); )+
+                Some(std::array::from_fn(|_| From::from((
+                $( $field_name.next().unwrap() ),+
+                ))))
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                self.slots.ids()
+            }
+
+            fn iter(&self) -> impl Iterator<Item=<$name as $crate::Component>::Ref<'_>>{
+                $crate::ComponentManager::iter_with_id(self).map(|(_, c)| c)
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=<$name as $crate::Component>::RefMut<'_>>{
+                self.sort_rows();
+                $crate::itertools::izip! {
+                $( self.$field_name.iter_mut() ),+
+                }
+                .map(From::from)
+            }
+
+            // picking the rows out in Id order, as they may be out of it
+            fn iter_with_id(&self) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                self.slots.rows().map(|(e, row)| (e, From::from((
+                $( &self.$field_name[row] ),+
+                ))))
+            }
+
+            fn shrink_to_fit(&mut self) {
+                self.slots.shrink_to_fit();
+                $( self.$field_name.shrink_to_fit(); )+
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_remove(f);
+            }
+        }
+        }
+    };
+
+    (@observers [$observers:ident] #[no_default] $($rest:tt)+) => {
+        $crate::component_sparse!(@manager [no_default] [$observers] $($rest)+);
+    };
+
+    (@observers [$observers:ident] $($rest:tt)+) => {
+        $crate::component_sparse!(@manager [default] [$observers] $($rest)+);
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::component_sparse!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::component_sparse!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+// Same as component!, but an entity may hold any number of the component at once (buffs, damage
+// instances, attached emitters...): the generated manager stores each field as a HashMap<Id, Vec<F>>
+// add appends another instance rather than replacing it, set replaces all of them with just the one,
+// and remove drops all of them (handing back the first, see remove_all for the rest)
+// iter, iter_mut and their _with_id counterparts visit every instance (an entity's instances in the
+// order they were added), while ids, len and contains count entities, not instances (see count)
+// get, get_mut and whatever's built on them (copy, queries looking the entity up...) only see the
+// first instance, use get_all and get_all_mut to reach the others
+// Tag components ({}) are not supported, an entity either has a tag or doesn't
+// Same caveats as component!
+#[macro_export]
+macro_rules! component_multi {
+    // internal, the whole manager, see component!'s #[no_default]
+    (@manager [$default:ident] [$observers:ident] $(#[$attr:meta])* $vis:vis struct $name:ident { $( $(#[slot($s:literal)])? $field_vis:vis $field_name:ident: $field_type:ty ),+ $(,)? }) => {
+        $crate::component!(@check_slots $name [$( ($($s)?) )+]);
+        $crate::component!(@types [$default] $(#[$attr])* $vis $name { $( $field_vis $field_name: $field_type, )+ });
+        $crate::component!(@debug_manager $name { $( $field_type ),+ });
+        $crate::component!(@into_iter $name);
+
+        $crate::paste::paste! {
+        $crate::serde_manager! {
+        #[derive(Default)]
+        $vis struct [< $name Manager >] {
+        $( $field_name: std::collections::HashMap<$crate::Id, Vec<$field_type>>, )+
+        hooks: $crate::ComponentHooks<$crate::$observers>,
+        }
+        }
+
+        impl [< $name Manager >] {
+            // Number of instances across every entity (len is the number of entities)
+            $vis fn count(&self) -> usize {
+                $crate::component!(@first_field self, $( $field_name ),+).values().map(Vec::len).sum()
+            }
+
+            // Every instance the entity holds, in the order they were added
+            $vis fn get_all(&self, e: $crate::Id) -> impl Iterator<Item=<$name as $crate::Component>::Ref<'_>> {
+                $crate::itertools::izip! {
+                $( self.$field_name.get(&e).into_iter().flatten() ),+
+                }
+                .map(From::from)
+            }
+
+            $vis fn get_all_mut(&mut self, e: $crate::Id) -> impl Iterator<Item=<$name as $crate::Component>::RefMut<'_>> {
+                $crate::itertools::izip! {
+                $( self.$field_name.get_mut(&e).into_iter().flatten() ),+
+                }
+                .map(From::from)
+            }
+
+            // Same as remove, handing back every instance instead of the first
+            $vis fn remove_all(&mut self, e: $crate::Id) -> Vec<$name> {
+                let had = $crate::ComponentManager::contains(self, e);
+                $(
+                let mut $field_name = self.$field_name.remove(&e).unwrap_or_default().into_iter();
+                )+
+
+                if had {
+                    self.hooks.removed(e);
+                }
+
+                std::iter::from_fn(|| Some($name {
+                    $( $field_name: $field_name.next()?, )+
+                }))
+                .collect()
+            }
+
+            // See component!
+            $(
+            $field_vis fn [< iter_where_ $field_name >](&self, mut pred: impl FnMut(&$field_type) -> bool) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                $crate::ComponentManager::iter_with_id(self).filter(move |(_, c)| pred(c.$field_name))
+            }
+            )+
+        }
+
+        impl $crate::Manages for [< $name Manager >] {
+            type Component = $name;
+        }
+
+        impl $crate::ComponentManager<$name> for [< $name Manager >] {
+            fn add(&mut self, e: $crate::Id, default: Option<$name>) {
+                if $crate::rejects_null(e) {
+                    return;
+                }
+
+                let had = $crate::ComponentManager::contains(self, e);
+                let default = $crate::component!(@or_default $default $name default);
+                $(
+                self.$field_name.entry(e).or_default().push(default.$field_name);
+                )+
+
+                if !had {
+                    self.hooks.added(e);
+                }
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<$name> {
+                self.remove_all(e).into_iter().next()
+            }
+
+            fn set(&mut self, e: $crate::Id, c: $name) -> Option<$name> {
+                if $crate::rejects_null(e) {
+                    return None;
+                }
+
+                let had = $crate::ComponentManager::contains(self, e);
+                $(
+                let $field_name = self.$field_name.insert(e, vec![c.$field_name]);
+                )+
+
+                if !had {
+                    self.hooks.added(e);
+                    return None;
+                }
+
+                Some($name {
+                    $( $field_name: $field_name?.into_iter().next()?, )+
+                })
+            }
+
+            fn clear(&mut self) {
+                let ids: Vec<$crate::Id> = $crate::ComponentManager::ids(self).collect();
+                $(
+                self.$field_name.clear();
+                )+
+
+                for e in ids {
+                    self.hooks.removed(e);
+                }
+            }
+
+            // every instance, unlike the default (which goes through remove)
+            fn drain(&mut self) -> impl Iterator<Item=($crate::Id, $name)> {
+                let ids: Vec<$crate::Id> = $crate::ComponentManager::ids(self).collect();
+                let drained: Vec<($crate::Id, $name)> = ids.into_iter().flat_map(|e| self.remove_all(e).into_iter().map(move |c| (e, c))).collect();
+                drained.into_iter()
+            }
+
+            // appends, so that extend(drain()) gives back what was drained
+            fn extend(&mut self, iter: impl IntoIterator<Item=($crate::Id, $name)>) {
+                for (e, c) in iter {
+                    $crate::ComponentManager::add(self, e, Some(c));
+                }
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                $crate::component!(@first_field self, $( $field_name ),+).contains_key(&e)
+            }
+
+            fn len(&self) -> usize {
+                $crate::component!(@first_field self, $( $field_name ),+).len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<<$name as $crate::Component>::Ref<'_>> {
+                Some(From::from((
+                $( self.$field_name.get(&e)?.first()? ),+
+                )))
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<<$name as $crate::Component>::RefMut<'_>> {
+                Some(From::from((
+                $( self.$field_name.get_mut(&e)?.first_mut()? ),+
+                )))
+            }
+
+            // moves every instance over, not just the first
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                if a == b {
+                    return;
+                }
+
+                let had = ($crate::ComponentManager::contains(self, a), $crate::ComponentManager::contains(self, b));
+                $(
+                let (x, y) = (self.$field_name.remove(&a), self.$field_name.remove(&b));
+                if let Some(x) = x {
+                    self.$field_name.insert(b, x);
+                }
+
+                if let Some(y) = y {
+                    self.$field_name.insert(a, y);
+                }
+                )+
+
+                match had {
+                    (true, false) => { self.hooks.removed(a); self.hooks.added(b); }
+                    (false, true) => { self.hooks.removed(b); self.hooks.added(a); }
+                    _ => {}
+                }
+            }
+
+            fn get_many_mut<const N: usize>(&mut self, ids: [$crate::Id; N]) -> Option<[<$name as $crate::Component>::RefMut<'_>; N]> {
+                if !$crate::disjoint(&ids) || !ids.iter().all(|e| self.contains(*e)) {
+                    return None;
+                }
+
+                $( let mut $field_name = self.$field_name.get_disjoint_mut(ids.each_ref()).into_iter().flatten().map(|v| &mut v[0]); )+
+                Some(std::array::from_fn(|_| From::from((
+                $( $field_name.next().unwrap() ),+
+                ))))
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                $crate::by_id(&$crate::component!(@first_field self, $( $field_name ),+)).map(|(e, _)| *e)
+            }
+
+            fn iter(&self) -> impl Iterator<Item=<$name as $crate::Component>::Ref<'_>>{
+                $crate::ComponentManager::iter_with_id(self).map(|(_, c)| c)
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=<$name as $crate::Component>::RefMut<'_>>{
+                $crate::ComponentManager::iter_mut_with_id(self).map(|(_, c)| c)
+            }
+
+            // an entity's instances are paired up by position, which (like component!'s iter)
+            // panics rather than mixing up entities should the field maps fall out of sync
+            fn iter_with_id(&self) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::Ref<'_>)> {
+                $crate::itertools::izip! {
+                $( $crate::by_id(&self.$field_name) ),+
+                }
+                .flat_map(|( $( ([< $field_name _id >], $field_name) ),+ )| {
+                    let e = *[ $( [< $field_name _id >] ),+ ][0];
+                    $crate::itertools::izip! {
+                    $( $field_name.iter().map(move |f| (*[< $field_name _id >], f)) ),+
+                    }
+                    .map(move |fields| (e, TryFrom::try_from(fields).unwrap_or_else(|err| panic!("{err}"))))
+                })
+            }
+
+            fn iter_mut_with_id(&mut self) -> impl Iterator<Item=($crate::Id, <$name as $crate::Component>::RefMut<'_>)> {
+                $crate::itertools::izip! {
+                $( $crate::by_id_mut(&mut self.$field_name) ),+
+                }
+                .flat_map(|( $( ([< $field_name _id >], $field_name) ),+ )| {
+                    let e = *[ $( [< $field_name _id >] ),+ ][0];
+                    $crate::itertools::izip! {
+                    $( $field_name.iter_mut().map(move |f| (*[< $field_name _id >], f)) ),+
+                    }
+                    .map(move |fields| (e, TryFrom::try_from(fields).unwrap_or_else(|err| panic!("{err}"))))
+                })
+            }
+
+            fn shrink_to_fit(&mut self) {
+                $(
+                self.$field_name.values_mut().for_each(Vec::shrink_to_fit);
+                self.$field_name.shrink_to_fit();
+                )+
+            }
+
+            type Observers = $crate::$observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<$crate::$observers>) {
+                self.hooks.on_remove(f);
+            }
+        }
+        }
+    };
+
+    (@observers [$observers:ident] #[no_default] $($rest:tt)+) => {
+        $crate::component_multi!(@manager [no_default] [$observers] $($rest)+);
+    };
+
+    (@observers [$observers:ident] $($rest:tt)+) => {
+        $crate::component_multi!(@manager [default] [$observers] $($rest)+);
+    };
+
+    (#[sync] $($rest:tt)+) => {
+        $crate::component_multi!(@observers [SendObservers] $($rest)+);
+    };
+
+    ($($rest:tt)+) => {
+        $crate::component_multi!(@observers [LocalObservers] $($rest)+);
+    };
+}
+
+// Joins several component managers by entity, visiting only the entities present in all of them
+// query! yields a tuple of Refs per entity (in the order the managers were given),
+// query_mut! yields a tuple of RefMuts
+// for (t, v) in query!(transforms, velocities) {
+//     // t: TransformRef, v: VelocityRef
+// }
+//
+// Wrapping a manager in Opt(...) makes its component optional, yielding an Option instead:
+// for (t, v) in query!(transforms, Opt(velocities)) {
+//     // t: TransformRef, v: Option<VelocityRef>, every entity with a Transform is visited
+// }
+//
+// Wrapping a manager in Without(...) skips the entities it holds a component for,
+// and yields nothing for it:
+// for (t,) in query!(transforms, Without(frozen)) {
+//     // t: TransformRef, only entities with a Transform but no Frozen component are visited
+// }
+//
+// At least one manager must be required, only the required managers decide which entities are visited
+// (minus those excluded with Without)
+// The smallest manager drives the iteration and the rest are looked up (or, for query_mut!,
+// walked alongside it, as get_mut can't hand out several RefMuts at once)
+// Entities are visited in ascending Id order
+// Each manager expression is evaluated more than once, so pass places (variables, self.field, ...)
+// At most 16 managers can be joined at once
+#[macro_export]
+macro_rules! query {
+    // internal, numbers the managers (so query_mut! can give each one a binding of its own)
+    // and splits them into those whose components are fetched, those that are required,
+    // and those that are excluded
+    (@parse $mode:tt [$($fetch:tt)*] [$($req:tt)*] [$($excl:tt)*] [$i:tt $($is:tt)*] Opt($e:expr) $(, $($rest:tt)*)?) => {
+        $crate::query!(@parse $mode [$($fetch)* ($i ($e) [])] [$($req)*] [$($excl)*] [$($is)*] $($($rest)*)?)
+    };
+
+    (@parse $mode:tt [$($fetch:tt)*] [$($req:tt)*] [$($excl:tt)*] [$i:tt $($is:tt)*] Without($e:expr) $(, $($rest:tt)*)?) => {
+        $crate::query!(@parse $mode [$($fetch)*] [$($req)*] [$($excl)* ($e)] [$($is)*] $($($rest)*)?)
+    };
+
+    (@parse $mode:tt [$($fetch:tt)*] [$($req:tt)*] [$($excl:tt)*] [$i:tt $($is:tt)*] $e:expr $(, $($rest:tt)*)?) => {
+        $crate::query!(@parse $mode [$($fetch)* ($i ($e) [?])] [$($req)* ($e)] [$($excl)*] [$($is)*] $($($rest)*)?)
+    };
+
+    (@parse $mode:tt [$($fetch:tt)*] [$($req:tt)*] [$($excl:tt)*] [$($is:tt)*]) => {
+        $crate::query!(@run $mode [$($fetch)*] [$($req)*] [$($excl)*])
+    };
+
+    // internal, the mode is either shared, mut, or (the cache) for query_cached!
+    (@run shared $($rest:tt)*) => {
+        $crate::query!(@shared $($rest)*)
+    };
+
+    (@run mut $($rest:tt)*) => {
+        $crate::query!(@mut $($rest)*)
+    };
+
+    (@run ($cache:expr) $($rest:tt)*) => {
+        $crate::query!(@cached ($cache) $($rest)*)
+    };
+
+    // internal, collects the Ids of the smallest required manager
+    (@driver [$( ($r:expr) )*]) => {{
+        let candidates: &mut [(usize, &mut dyn FnMut() -> Vec<$crate::Id>)] = &mut [
+        $( ($r.len(), &mut || $r.ids().collect()), )*
+        ];
+
+        candidates.iter_mut().min_by_key(|(len, _)| *len).map_or_else(Vec::new, |(_, ids)| ids())
+    }};
+
+    (@shared [$( ($i:tt ($f:expr) [$($q:tt)?]) )*] [$($req:tt)*] [$( ($x:expr) )*]) => {{
+        use $crate::ComponentManager as _;
+        let ids: Vec<$crate::Id> = $crate::query!(@driver [$($req)*]);
+
+        ids.into_iter()
+        $( .filter(|e| !$x.contains(*e)) )*
+        .filter_map(|e| {
+            Some(( $( $f.get(e) $($q)?, )* ))
+        })
+    }};
+
+    (@mut [$( ($i:tt ($f:expr) [$($q:tt)?]) )*] [$($req:tt)*] [$( ($x:expr) )*]) => {{
+        use $crate::ComponentManager as _;
+        let ids: Vec<$crate::Id> = $crate::query!(@driver [$($req)*]);
+
+        $crate::paste::paste! {
+        $(
+        let mut [< stream_ $i >] = $crate::Seek::new($f.iter_mut_with_id());
+        )*
+
+        ids.into_iter()
+        $( .filter(|e| !$x.contains(*e)) )*
+        .filter_map(move |e| {
+            Some(( $( [< stream_ $i >].seek(e) $($q)?, )* ))
+        })
+        }
+    }};
+
+    // the matching Ids are kept in the cache, and only looked for again once it is stale
+    (@cached ($cache:expr) [$( ($i:tt ($f:expr) [$($q:tt)?]) )*] [$( ($r:expr) )*] [$( ($x:expr) )*]) => {{
+        use $crate::ComponentManager as _;
+        let ids: &[$crate::Id] = $crate::QueryCache::ids_or_insert_with(&mut $cache, || {
+            let ids: Vec<$crate::Id> = $crate::query!(@driver [$( ($r) )*]);
+
+            ids.into_iter()
+            $( .filter(|e| $r.contains(*e)) )*
+            $( .filter(|e| !$x.contains(*e)) )*
+            .collect()
+        });
+
+        ids.iter().copied().filter_map(|e| {
+            Some(( $( $f.get(e) $($q)?, )* ))
+        })
+    }};
+
+    ($($args:tt)+) => {
+        $crate::query!(@parse shared [] [] [] [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15] $($args)+)
+    };
+}
+
+#[macro_export]
+macro_rules! query_mut {
+    ($($args:tt)+) => {
+        $crate::query!(@parse mut [] [] [] [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15] $($args)+)
+    };
+}
+
+// query!, remembering which entities matched in a QueryCache, see QueryCache
+// query_cached!(cache, transforms, Opt(velocities), Without(frozen))
+#[macro_export]
+macro_rules! query_cached {
+    ($cache:expr, $($args:tt)+) => {
+        $crate::query!(@parse ($cache) [] [] [] [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15] $($args)+)
+    };
+}
+
+// Checks (in debug builds only) that the managers hold components for exactly the same entities,
+// panicking otherwise
+// query! quietly skips entities that are missing from any required manager, which is what a join
+// should do, but also hides the "forgot to add a component" kind of bug in systems that expect
+// every entity to have all of their components:
+// debug_assert_same_entities!(transforms, velocities);
+// for (t, v) in query!(transforms, velocities) { ... }
+#[macro_export]
+macro_rules! debug_assert_same_entities {
+    ($first:expr $(, $rest:expr)+ $(,)?) => {
+        if cfg!(debug_assertions) {
+            use $crate::ComponentManager as _;
+            let first: Vec<$crate::Id> = $first.ids().collect();
+            $(
+            let rest: Vec<$crate::Id> = $rest.ids().collect();
+            if first != rest {
+                let odd = first.iter().find(|e| !$rest.contains(**e)).or_else(|| rest.iter().find(|e| !$first.contains(**e)));
+                panic!("{} and {} hold different entities ({:?} is only in one of them)", stringify!($first), stringify!($rest), odd.unwrap());
+            }
+            )+
+        }
+    };
+}
+
+// Registers several component managers with an entity manager in one go
+// register_components!(em, transforms, velocities, meshes);
+// is the same as calling em.register_component(&mut transforms) and so on
+// (the component types are inferred, so each manager must only manage one component type)
+#[macro_export]
+macro_rules! register_components {
+    ($em:expr, $( $m:expr ),+ $(,)?) => {
+        $( $em.register_component(&mut $m); )+
+    };
+}
+
+// See System
+// The managers are borrowed immutably, iter yields the same tuples query! does
+// (for mutable access, write the system by hand and use query_mut!)
+#[macro_export]
+macro_rules! system {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident { $( $field_vis:vis $field_name:ident: $manager:ty ),+ $(,)? }) => {
+        $(#[$attr])*
+        $vis struct $name<'a> {
+        $( $field_vis $field_name: &'a $manager, )+
+        }
+
+        impl<'a> $name<'a> {
+            $vis fn iter(&self) -> impl Iterator<Item=( $( <<$manager as $crate::Manages>::Component as $crate::Component>::Ref<'a>, )+ )> + '_ {
+                $crate::query!( $( self.$field_name ),+ )
+            }
+        }
+    };
+}
+
+// The managers generate more than any one test uses, hence the allow
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, HashSet};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    use crate::*;
+
+    component! {
+        #[derive(Clone, PartialEq, Debug)]
+        struct Transform {
+            x: i32,
+            y: i32,
+            name: String,
+        }
+    }
+
+    component! {
+        #[derive(Clone, Copy, PartialEq, Debug)]
+        struct Velocity {
+            dx: f32,
+            dy: f32,
+        }
+    }
+
+    component_dense! {
+        #[derive(Clone, Copy, PartialEq, Debug)]
+        struct Position {
+            x: f32,
+            y: f32,
+        }
+    }
+
+    component_fast! {
+        #[derive(Clone, PartialEq, Debug)]
+        struct FastTransform {
+            x: i32,
+            y: i32,
+            name: String,
+        }
+    }
+
+    component_sparse! {
+        #[derive(Clone, Copy, PartialEq, Debug)]
+        struct Mass {
+            kg: f32,
+            drag: f32,
+        }
+    }
+
+    fn transform(n: i32) -> Transform {
+        Transform { x: n, y: -n, name: format!("t{n}") }
+    }
+
+    // Ids that outlive their handles, so the test needn't keep the entities around
+    fn spawn_ids(em: &EntityManager, count: usize) -> Vec<Id> {
+        em.spawn_n(count).into_iter().map(Entity::into_id).collect()
+    }
+
+    #[test]
+    fn fields_stay_aligned_across_scrambled_inserts() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 8);
+        let mut transforms = TransformManager::default();
+        for i in [5, 2, 7, 0, 3, 6, 1, 4] {
+            transforms.add(ids[i], Some(transform(i as i32)));
+        }
+
+        for (i, e) in ids.iter().enumerate() {
+            assert_eq!(transforms.get(*e).map(Transform::from), Some(transform(i as i32)));
+        }
+
+        let all: Vec<(Id, Transform)> = transforms.iter_with_id().map(|(e, t)| (e, t.into())).collect();
+        let expected: Vec<(Id, Transform)> = ids.iter().enumerate().map(|(i, e)| (*e, transform(i as i32))).collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn get_and_get_mut_reach_one_entity() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        let mut transforms = TransformManager::default();
+        transforms.add(ids[0], Some(transform(1)));
+
+        let t = transforms.get_mut(ids[0]).unwrap();
+        *t.x += 10;
+        t.name.push('!');
+        let t = transforms.get(ids[0]).unwrap();
+        assert_eq!((*t.x, *t.y, t.name.as_str()), (11, -1, "t1!"));
+        assert!(transforms.get(ids[1]).is_none());
+        assert!(transforms.get_mut(ids[1]).is_none());
+    }
+
+    #[test]
+    fn contains_flips_on_add_and_remove() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        assert!(!transforms.contains(*e));
+        transforms.add(*e, None);
+        assert!(transforms.contains(*e));
+        transforms.remove(*e);
+        assert!(!transforms.contains(*e));
+    }
+
+    #[test]
+    fn despawn_cleans_up_the_registered_managers() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        let [a, b] = spawn_ids(&em, 2)[..] else { unreachable!() };
+        transforms.add(a, None);
+        transforms.add(b, None);
+        em.register_component(&mut transforms);
+
+        em.despawn(a);
+        assert!(!em.is_alive(a));
+        assert!(!em.has_component(a, ComponentId::of::<Transform>()));
+        assert!(em.has_component(b, ComponentId::of::<Transform>()));
+        drop(em);
+        assert_eq!(transforms.ids().collect::<Vec<_>>(), [b]);
+    }
+
+    #[test]
+    fn stale_ids_are_dead() {
+        let em = EntityManager::default();
+        let old = em.spawn().into_id();
+        em.despawn(old);
+        let new = em.spawn();
+        assert_eq!(new.raw(), old.raw());
+        assert_ne!(*new, old);
+        assert!(!em.is_alive(old));
+        assert!(em.is_alive(*new));
+    }
+
+    #[test]
+    fn dropping_a_handle_kills_its_entity() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let id = *e;
+        assert!(em.is_alive(id));
+        drop(e);
+        assert!(!em.is_alive(id));
+    }
+
+    #[test]
+    fn spawn_n_hands_out_contiguous_distinct_ids() {
+        let em = EntityManager::default();
+        drop(em.spawn());
+        let es = em.spawn_n(4);
+        let first = es[0].raw();
+        assert!(es.iter().enumerate().all(|(i, e)| e.raw() == first + i as u32));
+        assert_eq!(es.iter().map(|e| **e).collect::<HashSet<_>>().len(), 4);
+        drop(es);
+        assert!(em.is_empty());
+    }
+
+    #[test]
+    fn iter_ids_yields_the_live_entities() {
+        let em = EntityManager::default();
+        let mut es = em.spawn_n(3);
+        let dead = *es.remove(1);
+        assert_eq!(em.iter_ids().collect::<Vec<_>>(), [*es[0], *es[1]]);
+        assert!(!em.iter_ids().any(|e| e == dead));
+    }
+
+    #[test]
+    fn entity_and_component_counts_agree() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        for e in &ids {
+            transforms.add(*e, None);
+        }
+
+        transforms.remove(ids[0]);
+        assert_eq!((em.len(), transforms.len()), (5, 4));
+        em.register_component(&mut transforms);
+        em.despawn(ids[0]);
+        em.despawn(ids[1]);
+        let extra = em.spawn();
+        assert_eq!(em.len(), 4);
+        assert!(!em.is_empty());
+        drop(extra);
+        assert_eq!(em.len(), 3);
+        drop(em);
+        assert_eq!(transforms.len(), 3);
+    }
+
+    #[test]
+    fn resources_hold_one_value_per_type() {
+        let em = EntityManager::default();
+        assert!(em.resource::<f32>().is_none());
+        em.insert_resource(0.5f32);
+        em.insert_resource(String::from("level 1"));
+        *em.resource_mut::<f32>().unwrap() += 1.0;
+        em.insert_resource(String::from("level 2"));
+        assert_eq!(*em.resource::<f32>().unwrap(), 1.5);
+        assert_eq!(*em.resource::<String>().unwrap(), "level 2");
+    }
+
+    struct Nudge<'a> {
+        transforms: &'a mut TransformManager,
+    }
+
+    impl System for Nudge<'_> {
+        fn run(&mut self) {
+            for t in self.transforms.iter_mut() {
+                *t.x += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn systems_run_behind_dyn_system() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        transforms.add(*e, Some(transform(0)));
+
+        let mut systems: Vec<Box<dyn System>> = vec![Box::new(Nudge { transforms: &mut transforms })];
+        for _ in 0..3 {
+            for s in &mut systems {
+                s.run();
+            }
+        }
+
+        drop(systems);
+        assert_eq!(*transforms.get(*e).unwrap().x, 3);
+    }
+
+    #[test]
+    fn despawning_fires_on_remove_for_each_component() {
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let mut transforms = TransformManager::default();
+        let mut velocities = VelocityManager::default();
+        let log = Arc::clone(&removed);
+        transforms.on_remove(move |e| log.lock().unwrap().push(("transform", e)));
+        let log = Arc::clone(&removed);
+        velocities.on_remove(move |e| log.lock().unwrap().push(("velocity", e)));
+
+        let mut em = EntityManager::default();
+        let e = em.spawn().into_id();
+        transforms.add(e, None);
+        velocities.add(e, None);
+        em.register_component(&mut transforms);
+        em.register_component(&mut velocities);
+        em.despawn(e);
+        assert_eq!(*removed.lock().unwrap(), [("transform", e), ("velocity", e)]);
+    }
+
+    #[test]
+    fn remove_hands_back_the_component() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        transforms.add(*e, Some(transform(4)));
+        let t = transforms.remove(*e).unwrap();
+        assert_eq!((t.x, t.y, t.name.as_str()), (4, -4, "t4"));
+        assert!(transforms.remove(*e).is_none());
+    }
+
+    #[test]
+    fn set_replaces_in_place() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        assert_eq!(transforms.set(*e, transform(1)), None);
+        assert_eq!(transforms.set(*e, transform(2)), Some(transform(1)));
+        assert_eq!(transforms.len(), 1);
+        assert_eq!(transforms.get(*e).map(Transform::from), Some(transform(2)));
+    }
+
+    #[test]
+    fn query_visits_the_intersection_only() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 6);
+        let mut transforms = TransformManager::default();
+        let mut velocities = VelocityManager::default();
+        for (i, e) in ids.iter().enumerate().take(4) {
+            transforms.add(*e, Some(transform(i as i32)));
+        }
+        for (i, e) in ids.iter().enumerate().skip(2) {
+            velocities.add(*e, Some(Velocity { dx: i as f32, dy: 0.0 }));
+        }
+
+        let joined: Vec<(i32, f32)> = query!(transforms, velocities).map(|(t, v)| (*t.x, *v.dx)).collect();
+        assert_eq!(joined, [(2, 2.0), (3, 3.0)]);
+    }
+
+    #[test]
+    fn opt_yields_none_for_missing_components() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 4);
+        let mut transforms = TransformManager::default();
+        let mut velocities = VelocityManager::default();
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+            if i % 2 == 0 {
+                velocities.add(*e, Some(Velocity { dx: 1.0, dy: 1.0 }));
+            }
+        }
+
+        let joined: Vec<(i32, bool)> = query!(transforms, Opt(velocities)).map(|(t, v)| (*t.x, v.is_some())).collect();
+        assert_eq!(joined, [(0, true), (1, false), (2, true), (3, false)]);
+    }
+
+    #[test]
+    fn iter_with_id_pairs_each_component_with_its_entity() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let mut transforms = TransformManager::default();
+        for (i, e) in ids.iter().enumerate().rev() {
+            transforms.add(*e, Some(transform(i as i32)));
+        }
+
+        for (e, t) in transforms.iter_with_id() {
+            assert_eq!(*t.x, ids.iter().position(|id| *id == e).unwrap() as i32);
+        }
+
+        for (e, t) in transforms.iter_mut_with_id() {
+            *t.y = e.raw() as i32;
+        }
+
+        assert!(ids.iter().all(|e| *transforms.get(*e).unwrap().y == e.raw() as i32));
+    }
+
+    #[test]
+    fn observers_may_hold_on_to_anything_local() {
+        let added = Rc::new(RefCell::new(Vec::new()));
+        let mut transforms = TransformManager::default();
+        let log = Rc::clone(&added);
+        transforms.on_add(move |e| log.borrow_mut().push(e));
+
+        let em = EntityManager::default();
+        let e = em.spawn();
+        transforms.add(*e, None);
+        assert_eq!(*added.borrow(), [*e]);
+    }
+
+    #[test]
+    fn dense_storage_iterates_in_id_order() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 10_000);
+        let mut positions = PositionManager::default();
+        for (i, e) in ids.iter().enumerate() {
+            positions.add(*e, Some(Position { x: i as f32, y: 0.0 }));
+        }
+
+        assert_eq!(positions.len(), ids.len());
+        assert!(positions.ids().eq(ids.iter().copied()));
+        assert!(positions.iter().enumerate().all(|(i, p)| *p.x == i as f32));
+    }
+
+    #[test]
+    fn attributes_are_forwarded_to_the_struct() {
+        let t = transform(3);
+        let copy = t.clone();
+        assert_eq!(copy, t);
+        assert_eq!(format!("{t:?}"), r#"Transform { x: 3, y: -3, name: "t3" }"#);
+    }
+
+    mod shapes {
+        component! {
+            pub struct Radius {
+                pub r: f32,
+            }
+        }
+    }
+
+    #[test]
+    fn pub_components_are_usable_from_the_parent_module() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut radii = shapes::RadiusManager::default();
+        radii.add(*e, Some(shapes::Radius { r: 2.0 }));
+        let r: shapes::RadiusRef = radii.get(*e).unwrap();
+        assert_eq!(*r.r, 2.0);
+    }
+
+    component! {
+        struct Slotted {
+            #[slot(0)] first: u8,
+            #[slot(1)] second: u16,
+        }
+    }
+
+    #[test]
+    fn fields_are_numbered_in_declaration_order() {
+        let (x, y, name) = (1, 2, String::from("n"));
+        let t = TransformRef::from((&x, &y, &name));
+        assert_eq!((*t.x, *t.y, t.name.as_str()), (1, 2, "n"));
+
+        let (first, second) = (1u8, 2u16);
+        let s = SlottedRef::from((&first, &second));
+        assert_eq!((*s.first, *s.second), (1, 2));
+    }
+
+    component! {
+        struct NoComma {
+            a: i32,
+            b: i32
+        }
+    }
+
+    component! {
+        struct Comma {
+            a: i32,
+            b: i32,
+        }
+    }
+
+    #[test]
+    fn trailing_commas_are_optional() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let (mut without, mut with) = (NoCommaManager::default(), CommaManager::default());
+        without.add(*e, Some(NoComma { a: 1, b: 2 }));
+        with.add(*e, Some(Comma { a: 1, b: 2 }));
+        assert_eq!(*without.get(*e).unwrap().b, *with.get(*e).unwrap().b);
+    }
+
+    mod accounts {
+        component! {
+            pub struct Account {
+                pub owner: String,
+                balance: i64,
+            }
+        }
+
+        impl Account {
+            pub fn deposit(&mut self, n: i64) {
+                self.balance += n;
+            }
+
+            pub fn balance(&self) -> i64 {
+                self.balance
+            }
+        }
+    }
+
+    #[test]
+    fn private_fields_stay_usable_within_their_module() {
+        let mut account = accounts::Account::default();
+        account.owner.push_str("ann");
+        account.deposit(5);
+        account.deposit(7);
+        assert_eq!((account.owner.as_str(), account.balance()), ("ann", 12));
+    }
+
+    #[test]
+    fn cloned_entities_get_independent_copies() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        let src = em.spawn().into_id();
+        transforms.add(src, Some(transform(1)));
+        em.register_cloneable_component(&mut transforms);
+        let dst = em.clone_entity(src).into_id();
+        drop(em);
+
+        assert_eq!(transforms.get(dst).map(Transform::from), Some(transform(1)));
+        transforms.get_mut(dst).unwrap().name.push_str(" copy");
+        assert_eq!(transforms.get(src).map(Transform::from), Some(transform(1)));
+        assert_eq!(transforms.get(dst).unwrap().name, "t1 copy");
+    }
+
+    #[test]
+    fn adding_by_id_keeps_the_handle() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        transforms.add(*e, None);
+        velocities.add(*e, None);
+        assert!(em.is_alive(*e));
+        assert!(transforms.contains(*e) && velocities.contains(*e));
+    }
+
+    #[test]
+    fn with_capacity_preallocates_every_field() {
+        let transforms = TransformManager::with_capacity(100);
+        assert!(transforms.capacity() >= 100);
+        assert!(transforms.is_empty());
+        assert!(PositionManager::with_capacity(100).capacity() >= 100);
+    }
+
+    #[test]
+    fn clearing_a_manager_leaves_the_entities_alive() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let mut transforms = TransformManager::default();
+        for e in &ids {
+            transforms.add(*e, None);
+        }
+
+        transforms.clear();
+        assert_eq!(transforms.len(), 0);
+        assert!(ids.iter().all(|e| em.is_alive(*e)));
+    }
+
+    #[test]
+    fn debug_prints_field_names() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut velocities = VelocityManager::default();
+        velocities.add(*e, Some(Velocity { dx: 1.0, dy: 2.0 }));
+        assert_eq!(format!("{:?}", velocities.get(*e).unwrap()), "VelocityRef { dx: 1.0, dy: 2.0 }");
+        assert!(format!("{velocities:?}").contains("dx: 1.0"));
+    }
+
+    #[test]
+    fn iteration_is_in_ascending_id_order() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 50);
+        let mut transforms = TransformManager::default();
+        for e in ids.iter().rev().step_by(2).chain(ids.iter().step_by(2)) {
+            transforms.add(*e, None);
+        }
+
+        let order: Vec<Id> = transforms.ids().collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(order.len(), ids.len());
+    }
+
+    #[test]
+    fn spawn_with_id_restores_saved_ids() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        em.despawn(ids[1]);
+        let reused = em.spawn().into_id();
+        let mut transforms = TransformManager::default();
+        for (i, e) in [ids[0], reused, ids[2]].into_iter().enumerate() {
+            transforms.add(e, Some(transform(i as i32)));
+        }
+
+        let saved = transforms.collect_owned();
+        drop(em);
+
+        let em = EntityManager::default();
+        let mut loaded = TransformManager::default();
+        let handles: Vec<Entity> = saved.iter().map(|(e, _)| em.spawn_with_id(*e)).collect();
+        loaded.extend(saved.clone());
+        assert!(handles.iter().zip(&saved).all(|(h, (e, _))| **h == *e && em.is_alive(*e)));
+        assert_eq!(loaded.collect_owned(), saved);
+        assert_eq!(handles[1].generation(), 1);
+    }
+
+    #[test]
+    fn without_skips_the_excluded_entities() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 6);
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+            if i % 2 == 0 {
+                velocities.add(*e, None);
+            }
+        }
+
+        let visited: Vec<i32> = query!(transforms, Without(velocities)).map(|(t,)| *t.x).collect();
+        assert_eq!(visited, [1, 3, 5]);
+    }
+
+    component! {
+        struct Player {}
+    }
+
+    #[test]
+    fn tags_mark_entities_without_data() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let mut players = PlayerManager::default();
+        players.add(ids[0], None);
+        players.add(ids[2], Some(Player));
+
+        assert!(players.contains(ids[0]) && !players.contains(ids[1]));
+        assert_eq!(players.ids().collect::<Vec<_>>(), [ids[0], ids[2]]);
+        assert_eq!(players.iter().count(), 2);
+        assert_eq!(players.get(ids[1]), None);
+    }
+
+    #[test]
+    fn clearing_the_world_empties_every_manager() {
+        let mut transforms = TransformManager::default();
+        let mut velocities = VelocityManager::default();
+        let mut em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        for e in &ids {
+            transforms.add(*e, None);
+            velocities.add(*e, None);
+        }
+
+        em.register_component(&mut transforms);
+        em.register_component(&mut velocities);
+        em.clear();
+        assert!(em.is_empty());
+        assert!(ids.iter().all(|e| !em.is_alive(*e)));
+        // slots are reused, but under a new generation
+        assert_ne!(em.spawn().into_id(), ids[0]);
+
+        em.reset();
+        assert_eq!(em.spawn().into_id(), ids[0]);
+        drop(em);
+        assert_eq!((transforms.len(), velocities.len()), (0, 0));
+    }
+
+    #[test]
+    fn remove_hooks_may_despawn_other_entities() {
+        let em = Rc::new(EntityManager::default());
+        let (a, b) = (em.spawn().into_id(), em.spawn().into_id());
+        let mut transforms = TransformManager::default();
+        transforms.add(a, None);
+        transforms.add(b, None);
+
+        // a takes b down with it
+        let world = Rc::downgrade(&em);
+        transforms.on_remove(move |e| {
+            if let Some(em) = world.upgrade().filter(|_| e == a) {
+                em.despawn(b);
+            }
+        });
+
+        em.register_owned_component(transforms);
+        em.despawn(a);
+        assert!(!em.is_alive(b));
+        assert_eq!(em.manager::<TransformManager>().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn retain_drops_the_rejected_components_from_every_field() {
+        let em = EntityManager::default();
+        let mut transforms = TransformManager::default();
+        for e in spawn_ids(&em, 6) {
+            transforms.add(e, Some(transform(e.raw() as i32)));
+        }
+
+        transforms.retain(|e, _| e.raw() % 2 == 0);
+        assert!(transforms.ids().all(|e| e.raw() % 2 == 0));
+        assert_eq!((transforms.x.len(), transforms.y.len(), transforms.name.len()), (3, 3, 3));
+        assert!(transforms.name.keys().all(|e| e.raw() % 2 == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_iter_mut_visits_every_component_once() {
+        use rayon::iter::ParallelIterator;
+
+        let em = EntityManager::default();
+        let mut positions = PositionManager::default();
+        for e in spawn_ids(&em, 1000) {
+            positions.add(e, None);
+        }
+
+        positions.par_iter_mut().for_each(|p| *p.x += 1.0);
+        positions.par_for_each_mut(|p| *p.x += 1.0);
+        assert_eq!(positions.par_iter_mut().count(), 1000);
+        assert_eq!(positions.iter().map(|p| *p.x).sum::<f32>(), 2000.0);
+        assert!(positions.iter().all(|p| *p.y == 0.0));
+    }
+
+    #[test]
+    fn despawning_a_parent_takes_its_descendants_along() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        let [root, a, b, grandchild, bystander]: [Id; 5] = spawn_ids(&em, 5).try_into().unwrap();
+        em.set_parent(a, root);
+        em.set_parent(b, root);
+        em.set_parent(grandchild, a);
+        assert_eq!(em.children(root), [a, b]);
+        assert_eq!(em.parent(grandchild), Some(a));
+
+        for e in [root, a, b, grandchild, bystander] {
+            transforms.add(e, None);
+        }
+
+        em.register_component(&mut transforms);
+        em.despawn(root);
+        assert!([root, a, b, grandchild].iter().all(|e| !em.is_alive(*e)));
+        assert!(em.is_alive(bystander));
+        assert_eq!(em.children(root), []);
+        drop(em);
+        assert_eq!(transforms.ids().collect::<Vec<_>>(), [bystander]);
+    }
+
+    #[test]
+    fn try_spawn_fails_once_the_indices_run_out() {
+        let em = EntityManager::default();
+        em.0.borrow_mut().slots.max_index = 2;
+        let first = em.try_spawn().unwrap();
+        let _second = em.try_spawn().unwrap();
+        assert_eq!(em.try_spawn().err(), Some(SpawnError::Exhausted));
+        assert_eq!(em.len(), 2);
+
+        // freed slots are still up for reuse
+        drop(first);
+        assert!(em.try_spawn().is_ok());
+    }
+
+    #[test]
+    fn columns_read_one_field_while_writing_another() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let (mut transforms, mut positions) = (TransformManager::default(), PositionManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32 + 1)));
+            positions.add(*e, Some(Position { x: i as f32, y: 0.0 }));
+        }
+
+        let mut columns = transforms.columns();
+        for (e, x) in columns.x.iter() {
+            *columns.y.get_mut(e).unwrap() += 2 * *x;
+        }
+
+        let mut columns = positions.columns();
+        for (e, x) in columns.x.iter() {
+            *columns.y.get_mut(e).unwrap() = -*x;
+        }
+
+        assert_eq!(transforms.iter().map(|t| *t.y).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(positions.iter().map(|p| *p.y).collect::<Vec<_>>(), [0.0, -1.0, -2.0]);
+    }
+
+    #[test]
+    fn drain_hands_back_every_component_and_empties_the_manager() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 4);
+        let mut transforms = TransformManager::default();
+        for (i, e) in ids.iter().enumerate().rev() {
+            transforms.add(*e, Some(transform(i as i32)));
+        }
+
+        let drained: Vec<(Id, Transform)> = transforms.drain().collect();
+        assert_eq!(drained, ids.iter().enumerate().map(|(i, e)| (*e, transform(i as i32))).collect::<Vec<_>>());
+        assert!(transforms.is_empty());
+        assert!(transforms.x.is_empty() && transforms.y.is_empty() && transforms.name.is_empty());
+    }
+
+    system! {
+        struct Movement {
+            transforms: TransformManager,
+            velocities: VelocityManager,
+        }
+    }
+
+    #[test]
+    fn system_macro_joins_its_managers() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+        }
+
+        velocities.add(ids[0], Some(Velocity { dx: 1.0, dy: 0.0 }));
+        velocities.add(ids[2], Some(Velocity { dx: 3.0, dy: 0.0 }));
+
+        let movement = Movement { transforms: &transforms, velocities: &velocities };
+        let joined: Vec<(i32, f32)> = movement.iter().map(|(t, v)| (*t.x, *v.dx)).collect();
+        assert_eq!(joined, [(0, 1.0), (2, 3.0)]);
+    }
+
+    #[test]
+    fn registering_while_a_resource_is_held_fails() {
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let mut em = EntityManager::default();
+        let clone = em.clone();
+        em.insert_resource(0u32);
+
+        let held = clone.resource::<u32>().unwrap();
+        let err = em.try_register_component(&mut transforms).unwrap_err();
+        assert_eq!(err, RegisterError::Borrowed);
+        assert_eq!(err.to_string(), "cannot register component: the entity manager is already borrowed");
+        assert_eq!(em.try_register_owned_component(VelocityManager::default()), Err(RegisterError::Borrowed));
+
+        drop(held);
+        assert!(em.try_register_component(&mut velocities).is_ok());
+        assert_eq!(clone.registered_count(), 1);
+    }
+
+    #[test]
+    fn registered_managers_are_counted() {
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let mut em = EntityManager::default();
+        assert_eq!(em.registered_count(), 0);
+
+        em.register_component(&mut transforms);
+        em.register_component(&mut velocities);
+        assert_eq!(em.registered_count(), 2);
+        assert!(em.is_registered::<Transform>() && em.is_registered::<Velocity>());
+        assert!(!em.is_registered::<Position>());
+        assert_eq!(em.component_ids(), [ComponentId::of::<Transform>(), ComponentId::of::<Velocity>()]);
+    }
+
+    // (debug builds only, in release the check compiles to nothing)
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "hold different entities")]
+    fn mismatched_managers_fail_the_debug_check() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        transforms.add(ids[0], None);
+        transforms.add(ids[1], None);
+        velocities.add(ids[0], None);
+
+        debug_assert_same_entities!(transforms, transforms);
+        debug_assert_same_entities!(transforms, velocities);
+    }
+
+    #[test]
+    fn register_components_wires_up_every_manager() {
+        let (mut transforms, mut velocities, mut positions) = (TransformManager::default(), VelocityManager::default(), PositionManager::default());
+        let mut em = EntityManager::default();
+        let e = em.spawn().into_id();
+        transforms.add(e, None);
+        velocities.add(e, None);
+        positions.add(e, None);
+
+        register_components!(em, transforms, velocities, positions);
+        assert_eq!(em.registered_count(), 3);
+        em.despawn(e);
+        drop(em);
+        assert!(transforms.is_empty() && velocities.is_empty() && positions.is_empty());
+    }
+
+    #[test]
+    fn restoring_a_snapshot_brings_back_the_original_components() {
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let mut em = EntityManager::default();
+        let [a, b, c]: [Id; 3] = spawn_ids(&em, 3).try_into().unwrap();
+        transforms.add(a, Some(transform(1)));
+        transforms.add(b, Some(transform(2)));
+        velocities.add(a, Some(Velocity { dx: 1.0, dy: 1.0 }));
+
+        em.register_cloneable_component(&mut transforms);
+        em.register_cloneable_component(&mut velocities);
+        let (original, copy) = (em.snapshot_entity(a), em.snapshot_entity(a));
+        // a turns into a copy of b, losing its velocity
+        em.restore_entity(a, em.snapshot_entity(b));
+        assert!(!em.has_component(a, ComponentId::of::<Velocity>()));
+
+        em.restore_entity(c, copy);
+        em.restore_entity(a, original);
+        drop(em);
+
+        for e in [a, c] {
+            assert_eq!(Transform::from(transforms.get(e).unwrap()), transform(1));
+            assert_eq!(Velocity::from(velocities.get(e).unwrap()), Velocity { dx: 1.0, dy: 1.0 });
+        }
+    }
+
+    #[test]
+    fn into_id_keeps_the_entity_alive_until_despawned() {
+        let em = EntityManager::default();
+        em.register_owned_component(VelocityManager::default());
+
+        let e = {
+            let e = em.spawn();
+            em.manager_mut::<VelocityManager>().unwrap().add(*e, None);
+            e.into_id()
+        };
+
+        assert!(em.is_alive(e));
+        assert!(em.has_component(e, ComponentId::of::<Velocity>()));
+        em.despawn(e);
+        assert!(!em.is_alive(e));
+        assert!(em.manager::<VelocityManager>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_with_tweaks_a_default_component() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        transforms.add_with(*e, |t| t.y = 7);
+
+        assert_eq!(Transform::from(transforms.get(*e).unwrap()), Transform { y: 7, ..Default::default() });
+    }
+
+    #[test]
+    fn field_maps_allow_aggregates_over_one_field() {
+        let em = EntityManager::default();
+        let mut transforms = TransformManager::default();
+        for (i, e) in spawn_ids(&em, 4).into_iter().enumerate() {
+            transforms.add(e, Some(transform(i as i32 % 2)));
+        }
+
+        // a histogram of x
+        let mut histogram = [0; 2];
+        for x in transforms.x_map().values() {
+            histogram[*x as usize] += 1;
+        }
+
+        assert_eq!(histogram, [2, 2]);
+        assert_eq!(transforms.y_map().values().sum::<i32>(), -2);
+    }
+
+    #[test]
+    fn managers_can_be_looped_over_directly() {
+        let em = EntityManager::default();
+        let mut transforms = TransformManager::default();
+        for (i, e) in spawn_ids(&em, 3).into_iter().enumerate() {
+            transforms.add(e, Some(transform(i as i32)));
+        }
+
+        for t in &mut transforms {
+            *t.x *= 10;
+        }
+
+        let mut xs = Vec::new();
+        for t in &transforms {
+            xs.push(*t.x);
+        }
+
+        assert_eq!(xs, [0, 10, 20]);
+    }
+
+    #[test]
+    fn migrate_carries_components_over_to_the_new_type() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        let (mut velocities, mut transforms) = (VelocityManager::default(), TransformManager::default());
+        velocities.add(ids[0], Some(Velocity { dx: 1.0, dy: 2.0 }));
+        velocities.add(ids[1], Some(Velocity { dx: 3.0, dy: 4.0 }));
+
+        migrate(&velocities, &mut transforms, |v| Transform { x: *v.dx as i32, y: *v.dy as i32, name: String::from("migrated") });
+        assert_eq!(transforms.collect_owned(), [
+            (ids[0], Transform { x: 1, y: 2, name: String::from("migrated") }),
+            (ids[1], Transform { x: 3, y: 4, name: String::from("migrated") }),
+        ]);
+    }
+
+    #[test]
+    fn despawn_many_removes_exactly_the_given_entities() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        for e in &ids {
+            transforms.add(*e, None);
+        }
+
+        em.register_component(&mut transforms);
+        em.despawn(ids[3]);
+        // ids[3] is dead by now, and ids[1] is given twice
+        em.despawn_many(vec![ids[1], ids[3], ids[4], ids[1]]);
+        assert_eq!(em.iter_ids().collect::<Vec<_>>(), [ids[0], ids[2]]);
+        drop(em);
+        assert_eq!(transforms.ids().collect::<Vec<_>>(), [ids[0], ids[2]]);
+    }
+
+    #[test]
+    fn ids_start_past_null() {
+        let em = EntityManager::default();
+        assert!(!em.is_alive(EntityId::NULL));
+        assert_eq!(em.spawn().raw(), 1);
+        assert!(!em.is_alive(EntityId::NULL));
+    }
+
+    #[test]
+    fn null_never_holds_a_component() {
+        let (mut transforms, mut positions, mut players) = (TransformManager::default(), PositionManager::default(), PlayerManager::default());
+        // debug builds panic, release builds quietly ignore the component
+        let refused = [
+            panic::catch_unwind(AssertUnwindSafe(|| transforms.add(EntityId::NULL, None))).is_err(),
+            panic::catch_unwind(AssertUnwindSafe(|| transforms.set(EntityId::NULL, transform(0)))).is_err(),
+            panic::catch_unwind(AssertUnwindSafe(|| positions.add(EntityId::NULL, None))).is_err(),
+            panic::catch_unwind(AssertUnwindSafe(|| players.add(EntityId::NULL, None))).is_err(),
+        ];
+
+        assert_eq!(refused, [cfg!(debug_assertions); 4]);
+        assert!(!transforms.contains(EntityId::NULL) && transforms.get(EntityId::NULL).is_none());
+        assert!(!positions.contains(EntityId::NULL) && positions.get(EntityId::NULL).is_none());
+        assert!(!players.contains(EntityId::NULL));
+    }
+
+    #[test]
+    fn unregistered_managers_are_no_longer_cleaned_up() {
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let mut em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        for e in &ids {
+            transforms.add(*e, None);
+            velocities.add(*e, None);
+        }
+
+        let r = em.register_component(&mut transforms);
+        em.register_component(&mut velocities);
+        assert!(em.unregister_component(r));
+        assert!(!em.unregister_component(r));
+        assert_eq!(em.registered_count(), 1);
+
+        em.despawn_many(ids.clone());
+        drop(em);
+        assert_eq!(transforms.len(), 2);
+        assert!(velocities.is_empty());
+    }
+
+    #[test]
+    fn get_many_mut_hands_out_disjoint_components() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        let (mut transforms, mut positions) = (TransformManager::default(), PositionManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+            positions.add(*e, Some(Position { x: i as f32, y: 0.0 }));
+        }
+
+        let [a, b] = transforms.get_many_mut([ids[0], ids[1]]).unwrap();
+        std::mem::swap(a.name, b.name);
+        let [a, b] = positions.get_many_mut([ids[0], ids[1]]).unwrap();
+        std::mem::swap(a.x, b.x);
+
+        assert_eq!(transforms.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["t1", "t0"]);
+        assert_eq!(positions.iter().map(|p| *p.x).collect::<Vec<_>>(), [1.0, 0.0]);
+        assert!(transforms.get_many_mut([ids[0], ids[0]]).is_none());
+    }
+
+    #[test]
+    fn has_component_dispatches_on_the_component_id() {
+        let mut transforms = TransformManager::default();
+        let mut em = EntityManager::default();
+        em.register_owned_component(VelocityManager::default());
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        transforms.add(a, None);
+        em.manager_mut::<VelocityManager>().unwrap().add(b, None);
+        em.register_component(&mut transforms);
+
+        let (transform, velocity) = (ComponentId::of::<Transform>(), ComponentId::of::<Velocity>());
+        assert!(em.has_component(a, transform) && !em.has_component(a, velocity));
+        assert!(em.has_component(b, velocity) && !em.has_component(b, transform));
+        assert!(!em.has_component(a, ComponentId::of::<Position>()));
+    }
+
+    #[test]
+    fn extend_takes_back_what_drain_handed_out() {
+        let em = EntityManager::default();
+        let mut transforms = TransformManager::default();
+        for (i, e) in spawn_ids(&em, 3).into_iter().enumerate() {
+            transforms.add(e, Some(transform(i as i32)));
+        }
+
+        let before = transforms.collect_owned();
+        let mut copy = TransformManager::default();
+        copy.extend(transforms.drain());
+        assert!(transforms.is_empty());
+        assert_eq!(copy.collect_owned(), before);
+    }
+
+    #[test]
+    fn debug_prints_the_counts() {
+        let em = EntityManager::default();
+        em.register_owned_component(TransformManager::default());
+        let _entities = em.spawn_n(3);
+        assert_eq!(format!("{em:?}"), "EntityManager { live: 3, registered: 1 }");
+
+        let _held = em.0.borrow_mut();
+        assert_eq!(format!("{em:?}"), "EntityManager { .. }");
+    }
+
+    #[test]
+    fn collect_owned_gives_back_the_inserted_values() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let inserted: Vec<(Id, Transform)> = ids.iter().enumerate().map(|(i, e)| (*e, transform(i as i32))).collect();
+        let mut transforms = TransformManager::default();
+        for (e, t) in inserted.iter().rev() {
+            transforms.add(*e, Some(t.clone()));
+        }
+
+        assert_eq!(transforms.collect_owned(), inserted);
+    }
+
+    #[test]
+    fn dead_entities_cannot_receive_components() {
+        let em = EntityManager::default();
+        let (alive, dead) = (em.spawn().into_id(), em.spawn().into_id());
+        em.despawn(dead);
+        let mut transforms = TransformManager::default();
+        em.add_component(&mut transforms, alive, None);
+
+        assert_eq!(em.try_add_component(&mut transforms, dead, None), Err(AddError::Dead(dead)));
+        let added = panic::catch_unwind(AssertUnwindSafe(|| em.add_component(&mut transforms, dead, Some(transform(1)))));
+        assert!(added.is_err());
+        assert_eq!(transforms.ids().collect::<Vec<_>>(), [alive]);
+    }
+
+    #[test]
+    fn for_each_field_touches_that_field_only() {
+        let em = EntityManager::default();
+        let mut transforms = TransformManager::default();
+        for (i, e) in spawn_ids(&em, 3).into_iter().enumerate() {
+            transforms.add(e, Some(transform(i as i32 + 1)));
+        }
+
+        transforms.for_each_x(|x| *x *= 2);
+        assert_eq!(transforms.collect_owned().into_iter().map(|(_, t)| t).collect::<Vec<_>>(), [
+            Transform { x: 2, ..transform(1) },
+            Transform { x: 4, ..transform(2) },
+            Transform { x: 6, ..transform(3) },
+        ]);
+    }
+
+    #[test]
+    fn iter_snapshot_lets_the_loop_change_the_manager() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 4);
+        let mut transforms = TransformManager::default();
+        for (i, e) in ids.iter().take(3).enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+        }
+
+        for (e, t) in transforms.iter_snapshot() {
+            transforms.remove(e);
+            if t.x == 2 {
+                transforms.add(ids[3], Some(t));
+            }
+        }
+
+        assert_eq!(transforms.collect_owned(), [(ids[3], transform(2))]);
+    }
+
+    #[test]
+    fn merging_worlds_remaps_the_incoming_ids() {
+        let (mut here, mut there) = (TransformManager::default(), TransformManager::default());
+        let (mut a, mut b) = (EntityManager::default(), EntityManager::default());
+        let (ours, theirs) = (spawn_ids(&a, 2), spawn_ids(&b, 2));
+        // the worlds are apart, but their Ids collide
+        assert_ne!(a.world_id(), b.world_id());
+        assert_eq!(ours, theirs);
+        for (i, (x, y)) in ours.iter().zip(&theirs).enumerate() {
+            here.add(*x, Some(transform(i as i32)));
+            there.add(*y, Some(transform(10 + i as i32)));
+        }
+
+        a.register_cloneable_component(&mut here);
+        b.register_cloneable_component(&mut there);
+        let moved: Vec<(Id, Id)> = a.merge(&b).into_iter().map(|(old, new)| (old, new.into_id())).collect();
+        assert_eq!(moved.iter().map(|(old, _)| *old).collect::<Vec<_>>(), theirs);
+        assert!(moved.iter().all(|(_, new)| !ours.contains(new) && a.is_alive(*new)));
+        assert!(b.is_empty());
+
+        drop((a, b));
+        assert!(there.is_empty());
+        assert_eq!(here.collect_owned(), [
+            (ours[0], transform(0)),
+            (ours[1], transform(1)),
+            (moved[0].1, transform(10)),
+            (moved[1].1, transform(11)),
+        ]);
+    }
+
+    #[test]
+    fn spawn_builder_adds_each_component() {
+        let em = EntityManager::default();
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let e = em.spawn_builder()
+            .with(&mut transforms, transform(1))
+            .with(&mut velocities, Velocity { dx: 1.0, dy: 0.0 })
+            .build();
+
+        assert!(em.is_alive(*e));
+        assert_eq!(Transform::from(transforms.get(*e).unwrap()), transform(1));
+        assert_eq!(*velocities.get(*e).unwrap().dx, 1.0);
+    }
+
+    #[test]
+    fn capacity_outlasts_removals() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 1000);
+        let mut transforms = TransformManager::default();
+        for e in &ids {
+            transforms.add(*e, None);
+        }
+
+        assert!(transforms.capacity() >= 1000);
+        for e in &ids[10..] {
+            transforms.remove(*e);
+        }
+
+        // the maps keep (most of) their room, only shrink_to_fit gives it back
+        assert_eq!(transforms.len(), 10);
+        assert!(transforms.capacity() > 100 * transforms.len());
+    }
+
+    #[test]
+    fn shrink_to_fit_gives_back_the_spare_room() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 1000);
+        let (mut transforms, mut positions) = (TransformManager::default(), PositionManager::default());
+        for e in &ids {
+            transforms.add(*e, None);
+            positions.add(*e, None);
+        }
+
+        for e in &ids[10..] {
+            transforms.remove(*e);
+            positions.remove(*e);
+        }
+
+        let (before, dense_before) = (transforms.capacity(), positions.capacity());
+        transforms.shrink_to_fit();
+        positions.shrink_to_fit();
+        assert!(transforms.capacity() < before && transforms.capacity() >= 10);
+        assert!(positions.capacity() < dense_before && positions.capacity() >= 10);
+        assert_eq!(transforms.len() + positions.len(), 20);
+    }
+
+    #[test]
+    fn get_copied_hands_out_the_value() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut velocities = VelocityManager::default();
+        velocities.add(*e, Some(Velocity { dx: 0.5, dy: -0.5 }));
+
+        let v = velocities.get_copied(*e).unwrap();
+        assert_eq!(v, Velocity { dx: 0.5, dy: -0.5 });
+        *velocities.get_mut(*e).unwrap().dx = 9.0;
+        assert_eq!(v.dx, 0.5);
+        assert_eq!(velocities.get_copied(EntityId::NULL), None);
+    }
+
+    #[test]
+    fn cleanups_run_in_priority_order() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let (mut transforms, mut velocities, mut positions) = (TransformManager::default(), VelocityManager::default(), PositionManager::default());
+        let log = Rc::clone(&order);
+        transforms.on_remove(move |_| log.borrow_mut().push("transform"));
+        let log = Rc::clone(&order);
+        velocities.on_remove(move |_| log.borrow_mut().push("velocity"));
+        let log = Rc::clone(&order);
+        positions.on_remove(move |_| log.borrow_mut().push("position"));
+
+        let mut em = EntityManager::default();
+        let e = em.spawn().into_id();
+        transforms.add(e, None);
+        velocities.add(e, None);
+        positions.add(e, None);
+
+        em.register_component(&mut transforms);
+        em.register_component_with_priority(&mut velocities, 1);
+        em.register_component_with_priority(&mut positions, -1);
+        em.despawn(e);
+        assert_eq!(*order.borrow(), ["position", "transform", "velocity"]);
+    }
+
+    #[test]
+    fn entities_hash_by_their_id() {
+        let em = EntityManager::default();
+        let entities: HashSet<Entity> = em.spawn_n(3).into_iter().collect();
+        let ids: Vec<Id> = em.iter_ids().collect();
+
+        // a second handle to the same entity (one that leaves it be when dropped)
+        let twin = Entity::with_cleanup(ids[1], em.world_id(), |_| {});
+        assert!(entities.contains(&twin));
+        // the world isn't part of a handle's identity
+        let stranger = Entity::with_cleanup(ids[1], EntityManager::default().world_id(), |_| {});
+        assert!(entities.contains(&stranger));
+        assert!(ids.iter().all(|e| entities.contains(e)));
+        assert!(!entities.contains(&EntityId::NULL));
+    }
+
+    #[test]
+    fn sparse_storage_finds_the_rows_that_moved() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        let mut masses = MassManager::default();
+        for (i, e) in ids.iter().enumerate() {
+            masses.add(*e, Some(Mass { kg: i as f32, drag: 0.0 }));
+        }
+
+        // the last row fills the gap
+        assert_eq!(masses.remove(ids[1]), Some(Mass { kg: 1.0, drag: 0.0 }));
+        assert!(!masses.contains(ids[1]) && masses.get(ids[1]).is_none());
+        assert_eq!(*masses.get(ids[4]).unwrap().kg, 4.0);
+        *masses.get_mut(ids[4]).unwrap().drag = 0.5;
+        assert_eq!(masses.get_copied(ids[4]), Some(Mass { kg: 4.0, drag: 0.5 }));
+        assert_eq!(masses.len(), 4);
+    }
+
+    #[test]
+    fn sparse_storage_iterates_in_id_order_after_any_changes() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 200);
+        let (mut masses, mut expected) = (MassManager::default(), BTreeMap::new());
+        let mut seed = 7u32;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let e = ids[(seed >> 8) as usize % ids.len()];
+            if seed % 3 == 0 {
+                assert_eq!(masses.remove(e), expected.remove(&e));
+            } else {
+                let m = Mass { kg: seed as f32, drag: 1.0 };
+                masses.add(e, Some(m));
+                expected.insert(e, m);
+            }
+
+            if seed % 50 == 0 {
+                assert!(masses.ids().eq(expected.keys().copied()));
+            }
+        }
+
+        let expected: Vec<(Id, Mass)> = expected.into_iter().collect();
+        assert_eq!(masses.iter_with_id().map(|(e, m)| (e, Mass::from(m))).collect::<Vec<_>>(), expected);
+        assert!(masses.iter().map(Mass::from).eq(expected.iter().map(|(_, m)| *m)));
+        assert!(masses.iter_mut_with_id().map(|(e, _)| e).eq(expected.iter().map(|(e, _)| *e)));
+        masses.for_each_drag(|d| *d = 2.0);
+        let dragged: Vec<(Id, Mass)> = expected.iter().map(|(e, m)| (*e, Mass { drag: 2.0, ..*m })).collect();
+        assert_eq!(masses.collect_owned(), dragged);
+    }
+
+    #[test]
+    fn single_expects_at_most_one_component() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 2);
+        let mut transforms = TransformManager::default();
+        assert!(transforms.single().is_none());
+
+        transforms.add(ids[0], Some(transform(1)));
+        assert_eq!(*transforms.single().unwrap().x, 1);
+        *transforms.single_mut().unwrap().x = 2;
+        assert_eq!(*transforms.get(ids[0]).unwrap().x, 2);
+
+        // debug builds panic, release builds find none
+        transforms.add(ids[1], None);
+        let found = panic::catch_unwind(AssertUnwindSafe(|| transforms.single().is_some()));
+        assert_eq!(found.ok(), (!cfg!(debug_assertions)).then_some(false));
+    }
+
+    #[test]
+    fn swap_exchanges_or_moves_components() {
+        let em = EntityManager::default();
+        let [a, b, c]: [Id; 3] = spawn_ids(&em, 3).try_into().unwrap();
+        let mut transforms = TransformManager::default();
+        transforms.add(a, Some(transform(1)));
+        transforms.add(b, Some(transform(2)));
+
+        transforms.swap(a, b);
+        assert_eq!(transforms.collect_owned(), [(a, transform(2)), (b, transform(1))]);
+
+        // c has none, so b's moves over
+        transforms.swap(b, c);
+        assert_eq!(transforms.collect_owned(), [(a, transform(2)), (c, transform(1))]);
+    }
+
+    #[test]
+    fn add_default_with_id_derives_the_component_from_the_id() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 3);
+        let mut transforms = TransformManager::default();
+        for e in &ids {
+            transforms.add_default_with_id(*e, |e| Transform { x: e.raw() as i32, name: format!("{e:?}"), ..Default::default() });
+        }
+
+        for e in &ids {
+            let t = transforms.get(*e).unwrap();
+            assert_eq!((*t.x, t.name.as_str()), (e.raw() as i32, format!("{e:?}").as_str()));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn borrow_panics_point_at_the_borrow_in_the_way() {
+        let em = EntityManager::default();
+        em.insert_resource(0u32);
+        let e = em.spawn();
+
+        let line = line!() + 1;
+        let held = em.resource::<u32>().unwrap();
+        // EntityManager's own borrows don't count
+        assert!(em.is_alive(*e) && em.len() == 1 && em.iter_ids().count() == 1);
+
+        let conflict = panic::catch_unwind(AssertUnwindSafe(|| em.resource_mut::<u32>().is_some())).unwrap_err();
+        let message = conflict.downcast_ref::<String>().unwrap();
+        assert!(message.contains(&format!("(last borrowed at {}:{line}:", file!())), "{message}");
+        drop(held);
+
+        let line = line!() + 1;
+        let held = em.resource_mut::<u32>();
+        let conflict = panic::catch_unwind(AssertUnwindSafe(|| em.is_alive(*e))).unwrap_err();
+        let message = conflict.downcast_ref::<String>().unwrap();
+        assert!(message.contains(&format!("(mutably borrowed at {}:{line}:", file!())), "{message}");
+        drop(held);
+    }
+
+    #[test]
+    fn joins_yield_the_entities_in_every_manager() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 6);
+        let (mut transforms, mut velocities, mut positions, mut masses) =
+            (TransformManager::default(), VelocityManager::default(), PositionManager::default(), MassManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+            if i != 5 {
+                velocities.add(*e, None);
+            }
+            if i % 2 == 1 {
+                positions.add(*e, None);
+            }
+            if i != 3 {
+                masses.add(*e, None);
+            }
+        }
+
+        let found: Vec<Id> = join2(&transforms, &velocities).map(|(e, t, _)| { assert_eq!(*t.x as u32 + 1, e.raw()); e }).collect();
+        assert_eq!(found, ids[..5]);
+        let found: Vec<Id> = join3(&transforms, &velocities, &positions).map(|(e, ..)| e).collect();
+        assert_eq!(found, [ids[1], ids[3]]);
+        let found: Vec<Id> = join4(&transforms, &velocities, &positions, &masses).map(|(e, ..)| e).collect();
+        assert_eq!(found, [ids[1]]);
+    }
+
+    #[test]
+    fn fields_of_different_entities_are_not_assembled() {
+        let em = EntityManager::default();
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        let (x, y, name) = (1, 2, String::from("t"));
+
+        let t = TransformRef::try_from(((a, &x), (a, &y), (a, &name))).unwrap();
+        assert_eq!((*t.x, *t.y), (1, 2));
+        assert_eq!(TransformRef::try_from(((a, &x), (b, &y), (a, &name))).err(), Some(AssembleError::Mismatched(a, b)));
+
+        let (mut dx, mut dy) = (0.0, 0.0);
+        assert_eq!(VelocityRefMut::try_from(((b, &mut dx), (a, &mut dy))).err(), Some(AssembleError::Mismatched(b, a)));
+    }
+
+    #[test]
+    fn fast_managers_behave_like_the_standard_ones() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 500);
+        let (mut standard, mut fast) = (TransformManager::default(), FastTransformManager::default());
+        let mut seed = 11u32;
+        for _ in 0..10_000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let e = ids[(seed >> 8) as usize % ids.len()];
+            let n = (seed >> 16) as i32;
+            match seed % 4 {
+                0 => assert_eq!(standard.remove(e).map(|t| t.x), fast.remove(e).map(|t| t.x)),
+                1 => assert_eq!(standard.set(e, transform(n)).map(|t| t.x), fast.set(e, FastTransform { x: n, y: -n, name: format!("t{n}") }).map(|t| t.x)),
+                2 => {
+                    if let (Some(s), Some(f)) = (standard.get_mut(e), fast.get_mut(e)) {
+                        *s.y += 1;
+                        *f.y += 1;
+                    }
+                }
+                _ => assert_eq!(standard.contains(e), fast.contains(e)),
+            }
+        }
+
+        let standard: Vec<(Id, i32, i32, String)> = standard.drain().map(|(e, t)| (e, t.x, t.y, t.name)).collect();
+        let fast: Vec<(Id, i32, i32, String)> = fast.drain().map(|(e, t)| (e, t.x, t.y, t.name)).collect();
+        assert!(!standard.is_empty());
+        assert_eq!(standard, fast);
+    }
+
+    #[test]
+    fn components_without_a_default_are_added_explicitly() {
+        component! {
+            #[no_default]
+            struct Handle {
+                slot: std::num::NonZeroU32,
+                generation: u32,
+            }
+        }
+
+        let em = EntityManager::default();
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        let mut handles = HandleManager::default();
+        handles.add(a, Some(Handle { slot: std::num::NonZeroU32::new(7).unwrap(), generation: 1 }));
+        assert_eq!(handles.get(a).map(|h| (h.slot.get(), *h.generation)), Some((7, 1)));
+
+        let added = panic::catch_unwind(AssertUnwindSafe(|| handles.add(b, None)));
+        assert!(added.is_err());
+        assert!(!handles.contains(b));
+    }
+
+    #[test]
+    fn diff_reports_the_changes_since_a_snapshot() {
+        let (mut transforms, mut velocities) = (TransformManager::default(), VelocityManager::default());
+        let mut em = EntityManager::default();
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        transforms.add(a, Some(transform(1)));
+        transforms.add(b, Some(transform(2)));
+        velocities.add(a, Some(Velocity { dx: 1.0, dy: 1.0 }));
+
+        em.register_diffable_component(&mut transforms);
+        em.register_cloneable_component(&mut velocities);
+        let before = em.snapshot();
+        assert!(em.diff(&before, &em.snapshot()).is_empty());
+
+        // b turns into a copy of a (changing its transform, gaining a velocity), a goes,
+        // and c copies b (taking over a's index, which puts it before b)
+        em.restore_entity(b, em.snapshot_entity(a));
+        em.despawn(a);
+        let c = em.clone_entity(b).into_id();
+
+        let (t, v) = (ComponentId::of::<Transform>(), ComponentId::of::<Velocity>());
+        assert_eq!(em.diff(&before, &em.snapshot()), WorldDiff {
+            spawned: vec![c],
+            despawned: vec![a],
+            added: vec![(c, t), (c, v), (b, v)],
+            removed: vec![(a, t), (a, v)],
+            changed: vec![(b, t)],
+        });
+    }
+
+    #[test]
+    fn owned_managers_need_no_outside_storage() {
+        // the managers live and die with the entity manager, nothing else needs to outlive it
+        fn world() -> EntityManager<'static> {
+            let em = EntityManager::default();
+            em.register_owned_component(TransformManager::default());
+            em.register_owned_component(VelocityManager::default());
+            em
+        }
+
+        let em = world();
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        for (i, e) in [a, b].into_iter().enumerate() {
+            em.manager_mut::<TransformManager>().unwrap().add(e, Some(transform(i as i32)));
+        }
+        em.manager_mut::<VelocityManager>().unwrap().add(a, Some(Velocity { dx: 2.0, dy: 3.0 }));
+
+        let moves: Vec<(Id, Velocity)> = em.manager::<VelocityManager>().unwrap().iter_with_id().map(|(e, v)| (e, v.into())).collect();
+        let mut transforms = em.manager_mut::<TransformManager>().unwrap();
+        for (e, v) in moves {
+            let t = transforms.get_mut(e).unwrap();
+            *t.x += v.dx as i32;
+            *t.y += v.dy as i32;
+        }
+        drop(transforms);
+        assert_eq!(em.manager::<TransformManager>().unwrap().get(a).map(|t| (*t.x, *t.y)), Some((2, 3)));
+
+        em.despawn(a);
+        assert_eq!(em.manager::<TransformManager>().unwrap().ids().collect::<Vec<_>>(), [b]);
+        assert_eq!(em.manager::<VelocityManager>().unwrap().len(), 0);
+        assert!(em.manager::<PositionManager>().is_none());
+    }
+
+    #[test]
+    fn spawn_hooks_give_every_new_entity_a_component() {
+        let velocities = Rc::new(RefCell::new(VelocityManager::default()));
+        let despawned = Rc::new(RefCell::new(Vec::new()));
+        let em = EntityManager::default();
+        let old = em.spawn();
+
+        let hooked = Rc::clone(&velocities);
+        em.on_spawn(move |e| hooked.borrow_mut().add(e, Some(Velocity { dx: 1.0, dy: 0.0 })));
+        let log = Rc::clone(&despawned);
+        em.on_despawn(move |e| log.borrow_mut().push(e));
+
+        let one = em.spawn().into_id();
+        let many = spawn_ids(&em, 3);
+        let velocities = velocities.borrow();
+        assert!(!velocities.contains(*old));
+        assert_eq!(velocities.ids().collect::<Vec<_>>(), [&[one][..], &many].concat());
+        assert!(velocities.iter().all(|v| *v.dx == 1.0));
+
+        em.despawn(one);
+        drop(old);
+        assert_eq!(despawned.borrow().len(), 2);
+    }
+
+    #[test]
+    fn repair_drops_the_half_stored_components() {
+        let em = EntityManager::default();
+        let [a, b, c, d]: [Id; 4] = spawn_ids(&em, 4).try_into().unwrap();
+        let mut transforms = TransformManager::default();
+        for e in [a, b, c] {
+            transforms.add(e, Some(transform(e.raw() as i32)));
+        }
+
+        // a lost a field, d only ever got one
+        transforms.y.remove(&a);
+        transforms.name.insert(d, String::from("stray"));
+        assert_eq!(transforms.repair(), 2);
+        assert_eq!(transforms.repair(), 0);
+
+        assert_eq!((transforms.x.len(), transforms.y.len(), transforms.name.len()), (2, 2, 2));
+        let left: Vec<(Id, Transform)> = transforms.iter_with_id().map(|(e, t)| (e, t.into())).collect();
+        assert_eq!(left, [(b, transform(b.raw() as i32)), (c, transform(c.raw() as i32))]);
+    }
+
+    #[test]
+    fn driving_order_goes_from_the_smallest_manager_up() {
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        let (mut transforms, mut velocities, mut positions) = (TransformManager::default(), VelocityManager::default(), PositionManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, None);
+            if i < 2 {
+                velocities.add(*e, None);
+            }
+            if i < 4 {
+                positions.add(*e, None);
+            }
+        }
+
+        assert_eq!(driving_order([transforms.len(), velocities.len(), positions.len()]), [1, 2, 0]);
+        // ties keep their order
+        assert_eq!(driving_order([3, 1, 3, 1]), [1, 3, 0, 2]);
+        assert_eq!(driving_order::<0>([]), [0usize; 0]);
+    }
+
+    #[test]
+    fn dropping_a_scope_despawns_only_its_entities() {
+        let em = EntityManager::default();
+        em.register_owned_component(VelocityManager::default());
+        let outside = em.spawn();
+
+        let mut burst = em.scope();
+        let mut scoped = vec![burst.spawn()];
+        scoped.extend(burst.spawn_n(3));
+        let kept = em.spawn();
+        for e in scoped.iter().chain([&*outside, &*kept]) {
+            em.manager_mut::<VelocityManager>().unwrap().add(*e, None);
+        }
+
+        // despawning one early is fine
+        em.despawn(scoped[1]);
+        assert_eq!(burst.ids(), scoped);
+        drop(burst);
+
+        assert!(scoped.iter().all(|e| !em.is_alive(*e)));
+        assert!(em.is_alive(*outside) && em.is_alive(*kept));
+        assert_eq!(em.manager::<VelocityManager>().unwrap().ids().collect::<Vec<_>>(), [*outside, *kept]);
+    }
+
+    #[test]
+    fn try_add_refuses_to_overwrite() {
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let mut transforms = TransformManager::default();
+        assert_eq!(transforms.try_add(*e, Some(transform(1))), Ok(()));
+        assert_eq!(transforms.try_add(*e, Some(transform(2))), Err(AddError::Present(*e)));
+        assert_eq!(transforms.get(*e).map(Transform::from), Some(transform(1)));
+
+        transforms.add(*e, Some(transform(3)));
+        assert_eq!(transforms.get(*e).map(Transform::from), Some(transform(3)));
+    }
+
+    #[test]
+    fn iter_where_filters_on_one_field() {
+        component! {
+            struct Button {
+                enabled: bool,
+                label: String,
+            }
+        }
+
+        let em = EntityManager::default();
+        let ids = spawn_ids(&em, 5);
+        let mut buttons = ButtonManager::default();
+        for (i, e) in ids.iter().enumerate() {
+            buttons.add(*e, Some(Button { enabled: i % 2 == 0, label: format!("b{i}") }));
+        }
+
+        let enabled: Vec<(Id, &str)> = buttons.iter_where_enabled(|enabled| *enabled).map(|(e, b)| (e, b.label.as_str())).collect();
+        assert_eq!(enabled, [(ids[0], "b0"), (ids[2], "b2"), (ids[4], "b4")]);
+        assert_eq!(buttons.iter_where_label(|label| label.ends_with('3')).map(|(e, _)| e).collect::<Vec<_>>(), [ids[3]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "before any component manager was registered")]
+    fn strict_mode_flags_despawns_before_registration() {
+        let relaxed = EntityManager::default();
+        relaxed.despawn(relaxed.spawn().into_id());
+
+        let em = EntityManager::default();
+        em.set_strict(true);
+        em.despawn(em.spawn().into_id());
+    }
+
+    #[test]
+    fn multi_components_stack_on_one_entity() {
+        component_multi! {
+            #[derive(Clone, PartialEq, Debug)]
+            struct Buff {
+                stat: String,
+                amount: i32,
+            }
+        }
+
+        let em = EntityManager::default();
+        let [a, b]: [Id; 2] = spawn_ids(&em, 2).try_into().unwrap();
+        let mut buffs = BuffManager::default();
+        for (stat, amount) in [("str", 1), ("dex", 2), ("str", 3)] {
+            buffs.add(a, Some(Buff { stat: String::from(stat), amount }));
+        }
+        buffs.add(b, None);
+
+        let all: Vec<(Id, i32)> = buffs.iter_with_id().map(|(e, c)| (e, *c.amount)).collect();
+        assert_eq!(all, [(a, 1), (a, 2), (a, 3), (b, 0)]);
+        assert_eq!((buffs.len(), buffs.count()), (2, 4));
+        assert_eq!(buffs.get_all(a).map(|c| c.stat.as_str()).collect::<Vec<_>>(), ["str", "dex", "str"]);
+
+        buffs.remove(a);
+        assert!(!buffs.contains(a));
+        assert_eq!(buffs.count(), 1);
+    }
+
+    #[test]
+    fn weak_entities_die_with_their_entity() {
+        let (em, other) = (EntityManager::default(), EntityManager::default());
+        let e = em.spawn();
+        let twin = other.spawn();
+        let weak = e.downgrade();
+        assert_eq!(*e, *twin);
+        assert_eq!(weak.upgrade(&em), Some(*e));
+        assert_eq!(weak.upgrade(&em.clone()), Some(*e));
+        // same Id, different manager
+        assert!(!weak.is_alive(&other));
+        assert_eq!(weak.upgrade(&other), None);
+
+        drop(e);
+        assert!(!weak.is_alive(&em));
+        // the slot's next entity isn't mistaken for the old one
+        let _next = em.spawn();
+        assert_eq!(weak.upgrade(&em), None);
+    }
+
+    #[test]
+    fn spawn_with_id_keeps_stale_ids_dead() {
+        let em = EntityManager::default();
+        let stale = em.spawn().into_id();
+        em.despawn(stale);
+        let newer = em.spawn().into_id();
+        em.despawn(newer);
+
+        let revived = panic::catch_unwind(AssertUnwindSafe(|| em.spawn_with_id(stale).into_id()));
+        assert!(revived.is_err());
+        assert!(!em.is_alive(stale));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| em.spawn_with_id(newer).into_id())).is_err());
+        // the generation the slot is at (or any later one) is fine
+        let e = em.spawn_with_id(EntityId { index: newer.index, generation: newer.generation + 1 });
+        assert!(em.is_alive(*e) && !em.is_alive(stale) && !em.is_alive(newer));
+
+        // slots that ran out of generations stay retired, and no slot is made past the last index
+        let mut slots = Slots::default();
+        let last = EntityId { index: 1, generation: u32::MAX };
+        assert_eq!(slots.allocate_at(last), Ok(()));
+        assert!(slots.free(last));
+        assert!(slots.allocate_at(last).is_err());
+        assert!(slots.allocate_at(EntityId { index: 1, generation: 0 }).is_err());
+        slots.max_index = 3;
+        assert!(slots.allocate_at(EntityId { index: 4, generation: 0 }).is_err());
+        assert_eq!(slots.generations.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn managers_survive_a_json_round_trip() {
+        let em = EntityManager::default();
+        let mut ids = spawn_ids(&em, 4);
+        em.despawn(ids[1]);
+        ids[1] = em.spawn().into_id();
+        let (mut transforms, mut positions, mut masses) = (TransformManager::default(), PositionManager::default(), MassManager::default());
+        for (i, e) in ids.iter().enumerate() {
+            transforms.add(*e, Some(transform(i as i32)));
+            positions.add(*e, Some(Position { x: i as f32, y: 0.5 }));
+            masses.add(ids[ids.len() - 1 - i], Some(Mass { kg: i as f32, drag: 0.1 }));
+        }
+        transforms.remove(ids[2]);
+
+        let json = serde_json::to_string(&transforms).unwrap();
+        let loaded: TransformManager = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.collect_owned(), transforms.collect_owned());
+        assert_eq!(loaded.get(ids[1]).map(Transform::from), Some(transform(1)));
+        assert!(loaded.get(ids[2]).is_none());
+
+        let loaded: PositionManager = serde_json::from_str(&serde_json::to_string(&positions).unwrap()).unwrap();
+        assert_eq!(loaded.collect_owned(), positions.collect_owned());
+        let loaded: MassManager = serde_json::from_str(&serde_json::to_string(&masses).unwrap()).unwrap();
+        assert_eq!(loaded.collect_owned(), masses.collect_owned());
+    }
 }
@@ -0,0 +1,78 @@
+use std::cell::RefMut;
+use std::mem;
+
+use crate::EntityManager;
+
+// Events let systems communicate without knowing about each other
+// A producer sends events during its run, and a consumer drains them on the next frame
+// To that end the queue is double buffered:
+// send pushes onto this frame's buffer, drain pops from last frame's buffer,
+// and update (to be called once at the end of every frame) moves this frame's events over
+// Events that were not drained by the time the following frame ends are dropped
+pub struct EventQueue<E> {
+    current: Vec<E>,
+    previous: Vec<E>,
+}
+
+// Not derived, as that would require E: Default
+impl<E> Default for EventQueue<E> {
+    fn default() -> Self {
+        Self { current: Vec::new(), previous: Vec::new() }
+    }
+}
+
+impl<E> EventQueue<E> {
+    pub fn send(&mut self, e: E) {
+        self.current.push(e);
+    }
+
+    // Yields last frame's events, in the order they were sent
+    pub fn drain(&mut self) -> impl Iterator<Item=E> + '_ {
+        self.previous.drain(..)
+    }
+
+    pub fn update(&mut self) {
+        self.previous.clear();
+        mem::swap(&mut self.current, &mut self.previous);
+    }
+}
+
+// Event queues are meant to be stored as resources, one queue per event type
+impl<'a> EntityManager<'a> {
+    // Sends an event through the EventQueue<E> resource, inserting the queue on first use
+    pub fn send_event<E: 'static>(&self, e: E) {
+        if self.events::<E>().is_none() {
+            self.insert_resource(EventQueue::<E>::default());
+        }
+
+        self.events::<E>().unwrap().send(e);
+    }
+
+    #[track_caller]
+    pub fn events<E: 'static>(&self) -> Option<RefMut<'_, EventQueue<E>>> {
+        self.resource_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn events_arrive_next_frame_in_send_order() {
+        let em = EntityManager::default();
+        em.send_event(1);
+        em.send_event(2);
+        assert_eq!(em.events::<i32>().unwrap().drain().count(), 0);
+
+        em.events::<i32>().unwrap().update();
+        em.send_event(3);
+        assert_eq!(em.events::<i32>().unwrap().drain().collect::<Vec<_>>(), [1, 2]);
+
+        // undrained events are dropped after a frame
+        let mut events = em.events::<i32>().unwrap();
+        events.update();
+        events.update();
+        assert_eq!(events.drain().count(), 0);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{Component, ComponentManager, Id, Manages};
+
+// A read-only view of a component manager, see ComponentManager::freeze
+// Only the reading half of the manager is exposed (iter, get, contains and friends), so a system
+// handed a FrozenManager can't mutate the components, no matter what it does
+// It is Copy (and its methods take it by value, so what they return only borrows the manager),
+// so the same view can be handed to as many systems as needed
+/// ```compile_fail
+/// use ecs::*;
+///
+/// component! {
+///     struct Health {
+///         hp: i32,
+///     }
+/// }
+///
+/// let mut healths = HealthManager::default();
+/// for h in healths.freeze().iter_mut() {
+///     *h.hp += 1;
+/// }
+/// ```
+pub struct FrozenManager<'a, M> {
+    manager: &'a M,
+}
+
+// Not derived, as that would require M: Clone
+impl<M> Clone for FrozenManager<'_, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M> Copy for FrozenManager<'_, M> {}
+
+impl<'a, M> FrozenManager<'a, M> {
+    pub fn new(manager: &'a M) -> Self {
+        Self { manager }
+    }
+
+    pub fn contains<C: Component>(self, e: Id) -> bool
+    where M: ComponentManager<C> {
+        self.manager.contains(e)
+    }
+
+    pub fn len<C: Component>(self) -> usize
+    where M: ComponentManager<C> {
+        self.manager.len()
+    }
+
+    pub fn is_empty<C: Component>(self) -> bool
+    where M: ComponentManager<C> {
+        self.manager.is_empty()
+    }
+
+    pub fn get<C: Component>(self, e: Id) -> Option<C::Ref<'a>>
+    where M: ComponentManager<C> {
+        self.manager.get(e)
+    }
+
+    pub fn ids<C: Component + 'a>(self) -> impl Iterator<Item=Id> + 'a
+    where M: ComponentManager<C> {
+        self.manager.ids()
+    }
+
+    pub fn iter<C: Component>(self) -> impl Iterator<Item=C::Ref<'a>>
+    where M: ComponentManager<C> {
+        self.manager.iter()
+    }
+
+    pub fn iter_with_id<C: Component>(self) -> impl Iterator<Item=(Id, C::Ref<'a>)>
+    where M: ComponentManager<C> {
+        self.manager.iter_with_id()
+    }
+}
+
+impl<M: Manages> Manages for FrozenManager<'_, M> {
+    type Component = M::Component;
+}
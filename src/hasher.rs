@@ -0,0 +1,51 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+// A fast, non-cryptographic hasher for Ids (the same multiply-rotate scheme as rustc's FxHasher),
+// used by component_fast! managers
+// Not resistant to collision attacks in the least, only use it for keys nobody else gets to pick
+#[derive(Default, Clone, Copy)]
+pub struct IdHasher(u64);
+
+pub type BuildIdHasher = BuildHasherDefault<IdHasher>;
+
+impl IdHasher {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+    fn add(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(Self::SEED);
+    }
+}
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut n = [0; 8];
+            n[..chunk.len()].copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(n));
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.add(n as u64);
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.add(n as u64);
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.add(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.add(n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.add(n as u64);
+    }
+}
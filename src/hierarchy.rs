@@ -0,0 +1,139 @@
+use crate::{ComponentManager, EntityManager, Id};
+
+// Parent/child links between entities, kept as components so the hierarchy can be queried like
+// any other (query!(parents, transforms), iter_with_id, ...)
+// set_parent registers a ParentManager and a ChildrenManager as owned managers the first time it's
+// called (reach them through EntityManager::manager), and keeps the two in step from then on,
+// so only change them through set_parent and remove_parent
+// A despawn hook takes an entity's children along with it (see set_parent)
+crate::component! {
+    #[no_default]
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Parent {
+        pub id: Id,
+    }
+}
+
+crate::component! {
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct Children {
+        // in the order they were given their parent
+        pub ids: Vec<Id>,
+    }
+}
+
+impl<'a> EntityManager<'a> {
+    // Makes child a child of parent (replacing its previous parent, if any), so that child
+    // is destroyed along with parent
+    // Panics if either entity is dead, or if parent is child itself or one of its descendants
+    pub fn set_parent(&self, child: Id, parent: Id) {
+        assert!(self.is_alive(child) && self.is_alive(parent), "cannot parent {child:?} to {parent:?}: both must be alive");
+        assert!(!self.descends_from(parent, child), "cannot parent {child:?} to {parent:?}: it would create a cycle");
+        if self.manager::<ChildrenManager>().is_none() {
+            self.register_owned_component(ParentManager::default());
+            self.register_owned_component(ChildrenManager::default());
+            self.on_despawn_with_manager(despawn_children);
+        }
+
+        self.remove_parent(child);
+        self.manager_mut::<ParentManager>().unwrap().add(child, Some(Parent { id: parent }));
+        let mut children = self.manager_mut::<ChildrenManager>().unwrap();
+        match children.get_mut(parent) {
+            Some(siblings) => siblings.ids.push(child),
+            None => children.add(parent, Some(Children { ids: vec![child] })),
+        }
+    }
+
+    // Detaches child from its parent, it will no longer be destroyed along with it
+    pub fn remove_parent(&self, child: Id) {
+        let Some(parent) = self.manager_mut::<ParentManager>().and_then(|mut parents| parents.remove(child)) else {
+            return;
+        };
+
+        let Some(mut children) = self.manager_mut::<ChildrenManager>() else {
+            return;
+        };
+
+        if let Some(siblings) = children.get_mut(parent.id) {
+            siblings.ids.retain(|c| *c != child);
+            if siblings.ids.is_empty() {
+                children.remove(parent.id);
+            }
+        }
+    }
+
+    pub fn parent(&self, e: Id) -> Option<Id> {
+        self.manager::<ParentManager>()?.get(e).map(|p| *p.id)
+    }
+
+    // In the order they were given their parent
+    pub fn children(&self, e: Id) -> Vec<Id> {
+        self.manager::<ChildrenManager>().and_then(|children| Some(children.get(e)?.ids.clone())).unwrap_or_default()
+    }
+
+    // Whether ancestor is e or one of its ancestors
+    fn descends_from(&self, mut e: Id, ancestor: Id) -> bool {
+        loop {
+            if e == ancestor {
+                return true;
+            }
+
+            match self.parent(e) {
+                Some(parent) => e = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+// The despawn hook set_parent adds, unlinking e and despawning its children
+fn despawn_children(em: &EntityManager, e: Id) {
+    em.remove_parent(e);
+    let children = em.manager_mut::<ChildrenManager>().and_then(|mut children| children.remove(e));
+    if let Some(children) = children {
+        em.despawn_many(children.ids);
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        struct Name {
+            name: String,
+        }
+    }
+
+    #[test]
+    fn the_hierarchy_is_made_of_components() {
+        let mut names = NameManager::default();
+        let em = EntityManager::default();
+        let [root, a, b, leaf]: [Id; 4] = em.spawn_n(4).into_iter().map(Entity::into_id).collect::<Vec<_>>().try_into().unwrap();
+        for (e, name) in [(root, "root"), (a, "a"), (b, "b"), (leaf, "leaf")] {
+            names.add(e, Some(Name { name: String::from(name) }));
+        }
+
+        em.set_parent(a, root);
+        em.set_parent(b, root);
+        em.set_parent(leaf, a);
+        em.set_parent(leaf, b);
+
+        let parents = em.manager::<ParentManager>().unwrap();
+        let linked: Vec<(&str, Id)> = query!(names, parents).map(|(n, p)| (n.name.as_str(), *p.id)).collect();
+        assert_eq!(linked, [("a", root), ("b", root), ("leaf", b)]);
+        drop(parents);
+        assert_eq!(Children::from(em.manager::<ChildrenManager>().unwrap().get(root).unwrap()), Children { ids: vec![a, b] });
+        // leaf moved over to b, leaving a without children
+        assert_eq!(em.children(a), []);
+
+        em.remove_parent(b);
+        em.despawn(root);
+        assert!(!em.is_alive(a) && em.is_alive(b) && em.is_alive(leaf));
+        assert_eq!(em.manager::<ParentManager>().unwrap().ids().collect::<Vec<_>>(), [leaf]);
+        em.despawn(b);
+        assert!(!em.is_alive(leaf));
+        assert!(em.manager::<ChildrenManager>().unwrap().is_empty());
+    }
+}
@@ -1,5 +1,42 @@
 pub extern crate paste;
 pub extern crate itertools;
+#[cfg(feature = "serde")]
+pub extern crate serde;
+#[cfg(feature = "rayon")]
+pub extern crate rayon;
 
 mod ecs;
-pub use ecs::*;
\ No newline at end of file
+pub use ecs::*;
+
+mod events;
+pub use events::*;
+
+mod sync;
+pub use sync::*;
+
+mod tracked;
+pub use tracked::*;
+
+mod bundle;
+pub use bundle::*;
+
+mod commands;
+pub use commands::*;
+
+mod frozen;
+pub use frozen::*;
+
+mod archetype;
+pub use archetype::*;
+
+mod cache;
+pub use cache::*;
+
+mod versioned;
+pub use versioned::*;
+
+mod hasher;
+pub use hasher::*;
+
+mod hierarchy;
+pub use hierarchy::*;
\ No newline at end of file
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use crate::ecs::Slots;
+use crate::{Component, ComponentManager, Entity, Id, RegisterError, Registration, WorldId};
+
+type SyncCleanups<'a> = Vec<(Registration, Box<dyn FnMut(Id) + Send + 'a>)>;
+
+// Same as EntityManagerCore, minus the resources, with cleanups that may run on any thread
+#[derive(Default)]
+struct SyncEntityManagerCore<'a> {
+    slots: Slots,
+    // handed to the entities, see WeakEntity
+    world: WorldId,
+    next_registration: u64,
+    // locked apart from the core, see destroy
+    component_cleanups: Arc<Mutex<SyncCleanups<'a>>>,
+    // destroyed entities whose components are yet to be cleaned up
+    pending: VecDeque<Id>,
+}
+
+// Thread-safe counterpart to EntityManager, for spawning and despawning entities from several threads
+// (such as systems running on a thread pool)
+// Tradeoffs compared to EntityManager:
+// Every operation locks a Mutex, which is slower than a RefCell borrow even when uncontended
+// Registered component managers must be Send, as they are cleaned up from whichever thread destroys an entity
+// (the managers generated by component! and friends are when declared #[sync], see Observers)
+// Only the core lifecycle (spawning, despawning, liveness) is provided, there are no resources
+// Cleanups run with the manager unlocked, so they may destroy entities of their own: those are
+// cleaned up once the current cleanups are done (as with EntityManager), and so are entities
+// destroyed by another thread meanwhile, the thread already cleaning up gets to them
+// (despawn may thus return before the entity's components are gone)
+// Entity handles are not Send, but the manager is Sync, so share it (&SyncEntityManager) across threads
+// and spawn from the threads themselves
+#[derive(Default, Clone)]
+pub struct SyncEntityManager<'a>(Arc<Mutex<SyncEntityManagerCore<'a>>>);
+
+impl<'a> SyncEntityManager<'a> {
+    pub fn spawn(&self) -> Entity<'_> {
+        let (id, world) = {
+            let mut this = self.0.lock().unwrap();
+            (this.slots.allocate(), this.world)
+        };
+
+        Entity::with_cleanup(id, world, |n| self.destroy(n))
+    }
+
+    pub fn spawn_n(&self, count: usize) -> Vec<Entity<'_>> {
+        let (ids, world): (Vec<Id>, _) = {
+            let mut this = self.0.lock().unwrap();
+            ((0..count).map(|_| this.slots.allocate_fresh()).collect(), this.world)
+        };
+
+        ids.into_iter().map(|id| Entity::with_cleanup(id, world, |n| self.destroy(n))).collect()
+    }
+
+    // Panics from within a component cleanup, see try_register_component
+    pub fn register_component<C: Component, M: ComponentManager<C> + Send>(&self, m: &'a mut M) -> Registration {
+        self.try_register_component(m).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Registration fails while entities are being destroyed (by this thread or another)
+    pub fn try_register_component<C: Component, M: ComponentManager<C> + Send>(&self, m: &'a mut M) -> Result<Registration, RegisterError> {
+        let mut this = self.0.lock().unwrap();
+        let r = Registration(this.next_registration);
+        let mut cleanups = lock_cleanups(&this.component_cleanups).ok_or(RegisterError::Destroying)?;
+        cleanups.push((r, Box::new(|e| { m.remove(e); })));
+        drop(cleanups);
+        this.next_registration += 1;
+        Ok(r)
+    }
+
+    // See EntityManager::unregister_component
+    // Panics while entities are being destroyed
+    pub fn unregister_component(&self, r: Registration) -> bool {
+        let this = self.0.lock().unwrap();
+        let Some(mut cleanups) = lock_cleanups(&this.component_cleanups) else {
+            panic!("cannot unregister component: an entity is being destroyed");
+        };
+
+        let len = cleanups.len();
+        cleanups.retain(|(other, _)| *other != r);
+        cleanups.len() != len
+    }
+
+    pub fn is_alive(&self, e: Id) -> bool {
+        self.0.lock().unwrap().slots.is_alive(e)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter_ids(&self) -> impl Iterator<Item=Id> {
+        let ids = self.0.lock().unwrap().slots.ids();
+        ids.into_iter()
+    }
+
+    pub fn despawn(&self, e: Id) {
+        self.destroy(e);
+    }
+
+    fn destroy(&self, e: Id) {
+        let cleanups = {
+            let mut this = self.0.lock().unwrap();
+            if !this.slots.free(e) {
+                return;
+            }
+
+            this.pending.push_back(e);
+            Arc::clone(&this.component_cleanups)
+        };
+
+        // whoever holds on to the cleanups (further up this thread's stack, or on another thread)
+        // gets to e as well, checking for entities destroyed meanwhile once done
+        while let Some(mut cleanups) = lock_cleanups(&cleanups) {
+            while let Some(e) = self.next_pending() {
+                for (_, c) in cleanups.iter_mut() {
+                    c(e);
+                }
+            }
+
+            drop(cleanups);
+            if self.0.lock().unwrap().pending.is_empty() {
+                return;
+            }
+        }
+    }
+
+    fn next_pending(&self) -> Option<Id> {
+        self.0.lock().unwrap().pending.pop_front()
+    }
+}
+
+// The cleanups, unless they're running
+// (never waiting for them, a cleanup destroying an entity would wait on itself)
+fn lock_cleanups<'m, 'a>(cleanups: &'m Mutex<SyncCleanups<'a>>) -> Option<MutexGuard<'m, SyncCleanups<'a>>> {
+    match cleanups.try_lock() {
+        Ok(cleanups) => Some(cleanups),
+        Err(TryLockError::WouldBlock) => None,
+        Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use crate::*;
+
+    component! {
+        #[sync]
+        struct Health {
+            hp: i32,
+        }
+    }
+
+    #[test]
+    fn spawning_from_two_threads_hands_out_unique_ids() {
+        let em = SyncEntityManager::default();
+        let ids: Vec<Id> = thread::scope(|s| {
+            let spawners: Vec<_> = (0..2).map(|_| s.spawn(|| {
+                (0..500).map(|_| em.spawn().into_id()).collect::<Vec<_>>()
+            })).collect();
+
+            spawners.into_iter().flat_map(|t| t.join().unwrap()).collect()
+        });
+
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 1000);
+        assert_eq!(em.len(), 1000);
+    }
+
+    #[test]
+    fn sync_managers_are_cleaned_up_from_other_threads() {
+        let removed = Arc::new(AtomicUsize::new(0));
+        let mut health = HealthManager::default();
+        let count = Arc::clone(&removed);
+        health.on_remove(move |_| { count.fetch_add(1, Ordering::Relaxed); });
+
+        let em = SyncEntityManager::default();
+        let e = em.spawn().into_id();
+        health.add(e, Some(Health { hp: 3 }));
+        em.register_component(&mut health);
+        thread::scope(|s| { s.spawn(|| em.despawn(e)); });
+        drop(em);
+
+        assert!(!health.contains(e));
+        assert_eq!(removed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn registrations_can_be_undone() {
+        let (mut kept, mut dropped) = (HealthManager::default(), HealthManager::default());
+        let em = SyncEntityManager::default();
+        let e = em.spawn().into_id();
+        kept.add(e, None);
+        dropped.add(e, None);
+        em.register_component(&mut kept);
+        let r = em.register_component(&mut dropped);
+
+        assert!(em.unregister_component(r));
+        assert!(!em.unregister_component(r));
+        em.despawn(e);
+        drop(em);
+        assert!(!kept.contains(e));
+        assert!(dropped.contains(e));
+    }
+
+    #[test]
+    fn cleanups_may_use_the_manager() {
+        // the hooks hold on to the entity manager, which borrows their manager for good ('static)
+        let em = SyncEntityManager::default();
+        let [a, b] = [em.spawn().into_id(), em.spawn().into_id()];
+        let health = Box::leak(Box::new(HealthManager::default()));
+        health.add(a, None);
+        health.add(b, None);
+
+        // a takes b down with it, and registering from within a cleanup fails rather than deadlocking
+        let world = em.clone();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let removed = Arc::clone(&log);
+        health.on_remove(move |e| {
+            removed.lock().unwrap().push(e);
+            if e == a {
+                world.despawn(b);
+                let registered = world.try_register_component(Box::leak(Box::new(HealthManager::default())));
+                assert_eq!(registered, Err(RegisterError::Destroying));
+            }
+        });
+
+        em.register_component(health);
+        em.despawn(a);
+        assert!(!em.is_alive(b));
+        assert_eq!(*log.lock().unwrap(), [a, b]);
+        // the manager is usable again once the cleanups are done
+        assert!(em.try_register_component(Box::leak(Box::new(HealthManager::default()))).is_ok());
+    }
+}
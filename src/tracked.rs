@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use crate::{Component, ComponentManager, Id};
+
+// Wraps a component manager to keep track of which entities' components were modified,
+// for reactive systems that only care about what changed since their last pass
+// An entity counts as changed once its component is written to through the wrapper
+// (see wrapped_manager! for what counts as a write)
+// Nothing is cleared automatically, call clear_changed at the end of every pass (frame)
+#[derive(Default)]
+pub struct Tracked<M> {
+    manager: M,
+    changed: HashSet<Id>,
+}
+
+impl<M> Tracked<M> {
+    pub fn new(manager: M) -> Self {
+        Self { manager, changed: HashSet::new() }
+    }
+
+    pub fn is_changed(&self, e: Id) -> bool {
+        self.changed.contains(&e)
+    }
+
+    // The components changed since the last clear_changed, in ascending Id order
+    pub fn iter_changed<C: Component>(&self) -> impl Iterator<Item=(Id, C::Ref<'_>)>
+    where M: ComponentManager<C> {
+        let mut ids: Vec<Id> = self.changed.iter().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().filter_map(|e| Some((e, self.manager.get(e)?)))
+    }
+
+    pub fn clear_changed(&mut self) {
+        self.changed.clear();
+    }
+
+    // Read-only access to the wrapped manager, writes have to go through the wrapper to be tracked
+    pub fn inner(&self) -> &M {
+        &self.manager
+    }
+
+    pub fn into_inner(self) -> M {
+        self.manager
+    }
+}
+
+// What a wrapper around a manager (Tracked, Versioned) keeps on the side, see wrapped_manager!
+pub(crate) trait WriteLog {
+    // e's component was written to
+    fn written(&mut self, e: Id);
+    fn removed(&mut self, e: Id);
+    fn cleared(&mut self);
+    fn shrink_to_fit(&mut self);
+}
+
+impl WriteLog for HashSet<Id> {
+    fn written(&mut self, e: Id) {
+        self.insert(e);
+    }
+
+    fn removed(&mut self, e: Id) {
+        self.remove(&e);
+    }
+
+    fn cleared(&mut self) {
+        self.clear();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        HashSet::shrink_to_fit(self);
+    }
+}
+
+// Whether a write to a manager did anything, given whether e had the component before and after
+// Writes the manager turned down (NULL or dead Ids) leave e without one, and overwriting a tag
+// (a zero-sized component) with another changes nothing
+pub(crate) fn write_landed<C>(had: bool, has: bool) -> bool {
+    has && (!had || std::mem::size_of::<C>() != 0)
+}
+
+// Implements Manages and ComponentManager for a wrapper struct holding the manager in its
+// `manager` field alongside a WriteLog, which hears about every write that goes through the wrapper:
+// add and set (once the manager took the component, see write_landed), get_mut and get_many_mut
+// (when they find the components) and iter_mut (every entity it visits)
+// Everything else goes straight to the manager
+macro_rules! wrapped_manager {
+    ($wrapper:ident, $log:ident) => {
+        impl<M: $crate::Manages> $crate::Manages for $wrapper<M> {
+            type Component = M::Component;
+        }
+
+        impl<C: $crate::Component, M: $crate::ComponentManager<C>> $crate::ComponentManager<C> for $wrapper<M> {
+            fn add(&mut self, e: $crate::Id, c: Option<C>) {
+                let had = self.manager.contains(e);
+                self.manager.add(e, c);
+                if $crate::tracked::write_landed::<C>(had, self.manager.contains(e)) {
+                    $crate::tracked::WriteLog::written(&mut self.$log, e);
+                }
+            }
+
+            fn remove(&mut self, e: $crate::Id) -> Option<C> {
+                $crate::tracked::WriteLog::removed(&mut self.$log, e);
+                self.manager.remove(e)
+            }
+
+            fn set(&mut self, e: $crate::Id, c: C) -> Option<C> {
+                let had = self.manager.contains(e);
+                let old = self.manager.set(e, c);
+                if $crate::tracked::write_landed::<C>(had, self.manager.contains(e)) {
+                    $crate::tracked::WriteLog::written(&mut self.$log, e);
+                }
+
+                old
+            }
+
+            fn clear(&mut self) {
+                $crate::tracked::WriteLog::cleared(&mut self.$log);
+                self.manager.clear();
+            }
+
+            fn contains(&self, e: $crate::Id) -> bool {
+                self.manager.contains(e)
+            }
+
+            fn len(&self) -> usize {
+                self.manager.len()
+            }
+
+            fn get(&self, e: $crate::Id) -> Option<C::Ref<'_>> {
+                self.manager.get(e)
+            }
+
+            fn get_mut(&mut self, e: $crate::Id) -> Option<C::RefMut<'_>> {
+                let c = self.manager.get_mut(e)?;
+                $crate::tracked::WriteLog::written(&mut self.$log, e);
+                Some(c)
+            }
+
+            fn get_many_mut<const N: usize>(&mut self, ids: [$crate::Id; N]) -> Option<[C::RefMut<'_>; N]> {
+                let cs = self.manager.get_many_mut(ids)?;
+                for e in ids {
+                    $crate::tracked::WriteLog::written(&mut self.$log, e);
+                }
+
+                Some(cs)
+            }
+
+            fn ids(&self) -> impl Iterator<Item=$crate::Id> {
+                self.manager.ids()
+            }
+
+            fn iter(&self) -> impl Iterator<Item=C::Ref<'_>> {
+                self.manager.iter()
+            }
+
+            fn iter_mut(&mut self) -> impl Iterator<Item=C::RefMut<'_>> {
+                self.iter_mut_with_id().map(|(_, c)| c)
+            }
+
+            fn iter_mut_with_id(&mut self) -> impl Iterator<Item=($crate::Id, C::RefMut<'_>)> {
+                let log = &mut self.$log;
+                self.manager.iter_mut_with_id().inspect(move |(e, _)| $crate::tracked::WriteLog::written(log, *e))
+            }
+
+            fn swap(&mut self, a: $crate::Id, b: $crate::Id) {
+                self.manager.swap(a, b);
+                for e in [a, b] {
+                    if self.manager.contains(e) {
+                        $crate::tracked::WriteLog::written(&mut self.$log, e);
+                    } else {
+                        $crate::tracked::WriteLog::removed(&mut self.$log, e);
+                    }
+                }
+            }
+
+            fn shrink_to_fit(&mut self) {
+                $crate::tracked::WriteLog::shrink_to_fit(&mut self.$log);
+                self.manager.shrink_to_fit();
+            }
+
+            type Observers = M::Observers;
+
+            fn on_add(&mut self, f: impl $crate::Observer<M::Observers>) {
+                self.manager.on_add(f);
+            }
+
+            fn on_remove(&mut self, f: impl $crate::Observer<M::Observers>) {
+                self.manager.on_remove(f);
+            }
+        }
+    };
+}
+
+pub(crate) use wrapped_manager;
+
+wrapped_manager!(Tracked, changed);
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use crate::*;
+
+    component! {
+        struct Score {
+            points: i32,
+        }
+    }
+
+    #[test]
+    fn iter_changed_yields_only_the_mutated_entities() {
+        let em = EntityManager::default();
+        let ids: Vec<Id> = em.spawn_n(4).into_iter().map(Entity::into_id).collect();
+        let mut scores = Tracked::new(ScoreManager::default());
+        for e in &ids {
+            scores.add(*e, None);
+        }
+
+        scores.clear_changed();
+        *scores.get_mut(ids[1]).unwrap().points = 10;
+        *scores.get_mut(ids[3]).unwrap().points = 30;
+
+        let changed: Vec<(Id, i32)> = scores.iter_changed::<Score>().map(|(e, s)| (e, *s.points)).collect();
+        assert_eq!(changed, [(ids[1], 10), (ids[3], 30)]);
+        assert!(!scores.is_changed(ids[0]));
+    }
+}
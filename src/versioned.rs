@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use crate::tracked::{wrapped_manager, WriteLog};
+use crate::{Component, ComponentManager, Id};
+
+// Wraps a component manager to stamp every write with a version number, for delta sync
+// (a server sending clients only the components that changed since the last update they got)
+// Each write bumps the manager's version, and the entity written to takes on the new version
+// (see wrapped_manager! for what counts as a write)
+// Unlike Tracked, there is nothing to clear: remember current_version after every sync,
+// and hand it to iter_since on the next one (each client may be at a version of its own)
+// Removals aren't versioned, as there is no component left to send, track those separately
+// (with on_remove, for instance)
+#[derive(Default)]
+pub struct Versioned<M> {
+    manager: M,
+    versions: Versions,
+}
+
+// The version of every entity's latest write
+#[derive(Default)]
+struct Versions {
+    latest: u64,
+    of: HashMap<Id, u64>,
+}
+
+// The version isn't reset by clear, so versions handed out before the clear stay meaningful
+impl WriteLog for Versions {
+    fn written(&mut self, e: Id) {
+        self.latest += 1;
+        self.of.insert(e, self.latest);
+    }
+
+    fn removed(&mut self, e: Id) {
+        self.of.remove(&e);
+    }
+
+    fn cleared(&mut self) {
+        self.of.clear();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.of.shrink_to_fit();
+    }
+}
+
+impl<M> Versioned<M> {
+    pub fn new(manager: M) -> Self {
+        Self { manager, versions: Versions::default() }
+    }
+
+    // The version of the manager's latest write, 0 if nothing was written yet
+    pub fn current_version(&self) -> u64 {
+        self.versions.latest
+    }
+
+    // The version of e's latest write, None if e has no component
+    pub fn version(&self, e: Id) -> Option<u64> {
+        self.versions.of.get(&e).copied()
+    }
+
+    // The components written to after the given version, in ascending Id order
+    // iter_since(0) yields every component
+    pub fn iter_since<C: Component>(&self, version: u64) -> impl Iterator<Item=(Id, C::Ref<'_>)>
+    where M: ComponentManager<C> {
+        let mut ids: Vec<Id> = self.versions.of.iter().filter(|(_, v)| **v > version).map(|(e, _)| *e).collect();
+        ids.sort_unstable();
+        ids.into_iter().filter_map(|e| Some((e, self.manager.get(e)?)))
+    }
+
+    // Read-only access to the wrapped manager, writes have to go through the wrapper to be versioned
+    pub fn inner(&self) -> &M {
+        &self.manager
+    }
+
+    pub fn into_inner(self) -> M {
+        self.manager
+    }
+}
+
+wrapped_manager!(Versioned, versions);
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crate::*;
+
+    component! {
+        struct Pose {
+            angle: f32,
+        }
+    }
+
+    #[test]
+    fn iter_since_yields_what_was_written_after_the_version() {
+        let em = EntityManager::default();
+        let ids: Vec<Id> = em.spawn_n(4).into_iter().map(Entity::into_id).collect();
+        let mut poses = Versioned::new(PoseManager::default());
+        for e in &ids {
+            poses.add(*e, None);
+        }
+
+        let synced = poses.current_version();
+        *poses.get_mut(ids[2]).unwrap().angle = 1.0;
+        poses.set(ids[0], Pose { angle: 2.0 });
+        assert_eq!(poses.version(ids[2]), Some(synced + 1));
+
+        let changed: Vec<(Id, f32)> = poses.iter_since::<Pose>(synced).map(|(e, p)| (e, *p.angle)).collect();
+        assert_eq!(changed, [(ids[0], 2.0), (ids[2], 1.0)]);
+        assert_eq!(poses.iter_since::<Pose>(0).count(), 4);
+        assert_eq!(poses.iter_since::<Pose>(poses.current_version()).count(), 0);
+    }
+
+    #[test]
+    fn writes_that_change_nothing_keep_the_version() {
+        component! {
+            struct Flag {}
+        }
+
+        let em = EntityManager::default();
+        let e = em.spawn();
+        let (mut poses, mut flags) = (Versioned::new(PoseManager::default()), Versioned::new(FlagManager::default()));
+        let null = panic::catch_unwind(AssertUnwindSafe(|| poses.add(EntityId::NULL, None)));
+        // NULL is rejected outright in debug builds, and ignored in release ones
+        assert_eq!(null.is_err(), cfg!(debug_assertions));
+        assert_eq!(poses.current_version(), 0);
+
+        flags.add(*e, Some(Flag));
+        flags.add(*e, Some(Flag));
+        flags.set(*e, Flag);
+        assert_eq!(flags.current_version(), 1);
+
+        // overwriting a component with data is a write
+        poses.add(*e, None);
+        poses.add(*e, Some(Pose { angle: 1.0 }));
+        assert_eq!(poses.version(*e), Some(2));
+    }
+}